@@ -0,0 +1,253 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{Document, SourceInfo};
+
+/// Where the chunker prefers to end a chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkBoundary {
+    /// Blank lines first, then sentence ends, then anywhere
+    Paragraph,
+    /// Sentence ends or blank lines, then anywhere
+    Sentence,
+    /// Always fill the chunk to `max_tokens`
+    Token,
+}
+
+/// Options for splitting documents into chunks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkerConfig {
+    /// Largest chunk, in tokens as counted by `count_tokens`
+    pub max_tokens: usize,
+    /// Tokens repeated at the start of a chunk from the end of the previous one
+    pub overlap_tokens: usize,
+    pub boundary: ChunkBoundary,
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: 200,
+            overlap_tokens: 20,
+            boundary: ChunkBoundary::Paragraph,
+        }
+    }
+}
+
+/// Byte ranges of the tokens of `text`: runs of alphanumerics, or single punctuation marks
+pub fn token_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut word_start: Option<usize> = None;
+    for (at, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            word_start.get_or_insert(at);
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            spans.push((start, at));
+        }
+        if !c.is_whitespace() {
+            spans.push((at, at + c.len_utf8()));
+        }
+    }
+    if let Some(start) = word_start {
+        spans.push((start, text.len()));
+    }
+    spans
+}
+
+/// Number of tokens in `text`; the unit of `max_tokens` and `TextNode::token_count`
+pub fn count_tokens(text: &str) -> usize {
+    token_spans(text).len()
+}
+
+/// Split every document into chunks of at most `max_tokens` tokens
+///
+/// Chunk text is a verbatim slice of the document text. `chunk_idx` counts
+/// chunks per file from 0, in document order, and `char_range` is set to the
+/// chunk's character offsets: into the original file when the document
+/// carries its own `char_range`, otherwise into the document text. Documents
+/// without tokens are dropped.
+pub fn chunk_documents(documents: &[Document], config: &ChunkerConfig) -> Vec<Document> {
+    let mut next_idx: HashMap<&str, usize> = HashMap::new();
+    let mut chunks = Vec::new();
+    for doc in documents {
+        let base = doc.source.char_range.map_or(0, |(start, _)| start);
+        for (text, (start, end)) in chunk_text(&doc.text, config) {
+            let idx = next_idx.entry(doc.source.filename.as_str()).or_insert(0);
+            chunks.push(Document {
+                text: text.to_string(),
+                source: SourceInfo {
+                    chunk_idx: Some(*idx),
+                    char_range: Some((base + start, base + end)),
+                    ..doc.source.clone()
+                },
+                metadata: doc.metadata.clone(),
+            });
+            *idx += 1;
+        }
+    }
+    chunks
+}
+
+/// Chunks of `text` with their character ranges, end exclusive
+pub fn chunk_text<'a>(text: &'a str, config: &ChunkerConfig) -> Vec<(&'a str, (usize, usize))> {
+    let spans = token_spans(text);
+    let max_tokens = config.max_tokens.max(1);
+    let overlap = config.overlap_tokens.min(max_tokens - 1);
+
+    let mut starts = CharCursor::default();
+    let mut ends = CharCursor::default();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < spans.len() {
+        let end = chunk_end(text, &spans, start, max_tokens, config.boundary);
+        let (from, to) = (spans[start].0, spans[end - 1].1);
+        chunks.push((&text[from..to], (starts.advance(text, from), ends.advance(text, to))));
+        if end == spans.len() {
+            break;
+        }
+        start = end.saturating_sub(overlap).max(start + 1);
+    }
+    chunks
+}
+
+/// Token index one past the end of the chunk that starts at token `start`
+///
+/// Boundaries are only taken in the second half of the token budget, so a
+/// boundary right after the start does not produce a tiny chunk.
+fn chunk_end(text: &str, spans: &[(usize, usize)], start: usize, max_tokens: usize, boundary: ChunkBoundary) -> usize {
+    let limit = start + max_tokens;
+    if limit >= spans.len() {
+        return spans.len();
+    }
+    let earliest = start + max_tokens.div_ceil(2);
+    // A boundary after token `i - 1` ends the chunk at `i`
+    let gap = |i: usize| &text[spans[i - 1].1..spans[i].0];
+    let paragraph = |i: usize| gap(i).matches('\n').count() >= 2;
+    let sentence = |i: usize| {
+        let last = &text[spans[i - 1].0..spans[i - 1].1];
+        matches!(last, "." | "!" | "?") && !gap(i).is_empty()
+    };
+    let find = |accept: &dyn Fn(usize) -> bool| (earliest..=limit).rev().find(|&i| accept(i));
+
+    let found = match boundary {
+        ChunkBoundary::Paragraph => find(&paragraph).or_else(|| find(&sentence)),
+        ChunkBoundary::Sentence => find(&|i| sentence(i) || paragraph(i)),
+        ChunkBoundary::Token => None,
+    };
+    found.unwrap_or(limit)
+}
+
+/// Converts increasing byte offsets of one string to character offsets
+#[derive(Debug, Default)]
+pub(crate) struct CharCursor {
+    byte: usize,
+    chars: usize,
+}
+
+impl CharCursor {
+    /// Character offset of byte offset `at`, which must not be before the previous call
+    pub(crate) fn advance(&mut self, text: &str, at: usize) -> usize {
+        self.chars += text[self.byte..at].chars().count();
+        self.byte = at;
+        self.chars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+
+    fn config(max_tokens: usize, overlap_tokens: usize, boundary: ChunkBoundary) -> ChunkerConfig {
+        ChunkerConfig {
+            max_tokens,
+            overlap_tokens,
+            boundary,
+        }
+    }
+
+    fn document(filename: &str, text: &str) -> Document {
+        Document {
+            text: text.to_string(),
+            source: SourceInfo {
+                filename: filename.to_string(),
+                page_num: Some(1),
+                file_type: "txt".to_string(),
+                chunk_idx: None,
+                char_range: None,
+                tags: Vec::new(),
+            },
+            metadata: Metadata::new(),
+        }
+    }
+
+    #[test]
+    fn test_token_spans() {
+        assert_eq!(count_tokens("Solar panels, in 2024: 3.5kW!"), 10);
+        assert_eq!(token_spans("héllo wörld"), vec![(0, 6), (7, 13)]);
+        assert_eq!(count_tokens("  \n "), 0);
+    }
+
+    #[test]
+    fn test_prefers_paragraph_then_sentence() {
+        let text = "One two three. Four five\n\nsix seven eight. Nine ten eleven twelve";
+        let chunks: Vec<&str> = chunk_text(text, &config(8, 0, ChunkBoundary::Paragraph))
+            .into_iter()
+            .map(|(chunk, _)| chunk)
+            .collect();
+        assert_eq!(chunks, vec!["One two three. Four five", "six seven eight. Nine ten eleven twelve"]);
+
+        let chunks: Vec<&str> = chunk_text(text, &config(8, 0, ChunkBoundary::Sentence))
+            .into_iter()
+            .map(|(chunk, _)| chunk)
+            .collect();
+        assert_eq!(chunks[0], "One two three. Four five");
+
+        let chunks: Vec<&str> = chunk_text("a b c d e f g h i j", &config(4, 0, ChunkBoundary::Sentence))
+            .into_iter()
+            .map(|(chunk, _)| chunk)
+            .collect();
+        assert_eq!(chunks, vec!["a b c d", "e f g h", "i j"]);
+    }
+
+    #[test]
+    fn test_overlap_repeats_tokens() {
+        let chunks: Vec<&str> = chunk_text("a b c d e f g", &config(4, 2, ChunkBoundary::Token))
+            .into_iter()
+            .map(|(chunk, _)| chunk)
+            .collect();
+        assert_eq!(chunks, vec!["a b c d", "c d e f", "e f g"]);
+
+        // Overlap as large as the chunk still makes progress
+        let chunks = chunk_text("a b c", &config(2, 5, ChunkBoundary::Token));
+        assert_eq!(chunks.len(), 2);
+    }
+
+    #[test]
+    fn test_char_ranges_and_chunk_idx() {
+        let text = "café au lait. Crème brûlée tonight";
+        let mut first = document("menu.txt", text);
+        first.source.char_range = Some((100, 100 + text.chars().count()));
+        let docs = vec![first, document("other.txt", "x y"), document("menu.txt", "more text")];
+
+        let chunks = chunk_documents(&docs, &config(4, 0, ChunkBoundary::Sentence));
+        let ids: Vec<_> = chunks.iter().map(|c| (c.source.filename.as_str(), c.source.chunk_idx)).collect();
+        assert_eq!(
+            ids,
+            vec![("menu.txt", Some(0)), ("menu.txt", Some(1)), ("other.txt", Some(0)), ("menu.txt", Some(2))]
+        );
+
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in &chunks[..2] {
+            let (start, end) = chunk.source.char_range.unwrap();
+            let expected: String = chars[start - 100..end - 100].iter().collect();
+            assert_eq!(chunk.text, expected);
+        }
+        assert_eq!(chunks[1].text, "Crème brûlée tonight");
+        assert_eq!(chunks[2].source.char_range, Some((0, 3)));
+    }
+}
@@ -0,0 +1,299 @@
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::lexical::lexical_tokens;
+use crate::NumpyGraphStore;
+
+/// What `add_documents` does with a text nearly identical to one already seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NearDuplicateMode {
+    /// Only exact duplicates are merged
+    Off,
+    /// Merge into the earlier text as an exact duplicate would be: its source
+    /// and metadata join the earlier text's `other_sources` and
+    /// `other_metadata`, and its own text is dropped
+    Merge,
+    /// Keep the text and log the pair; `near_duplicates` lists every pair for review
+    Report,
+}
+
+/// Near-duplicate detection by MinHash over word shingles, with LSH banding
+///
+/// Texts are compared on their lowercased alphanumeric tokens, so whitespace
+/// and punctuation differences are ignored. Two texts are near-duplicates
+/// when the estimated Jaccard similarity of their shingle sets reaches
+/// `threshold`. Only pairs sharing one of `bands` bands of `rows` hashes are
+/// compared; the chance of that rises steeply around
+/// `(1 / bands) ^ (1 / rows)`, which should lie below `threshold`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DedupConfig {
+    pub near_duplicates: NearDuplicateMode,
+    /// Smallest estimated Jaccard similarity, in [0, 1], of near-duplicates
+    pub threshold: f32,
+    /// Words per shingle; texts shorter than this form a single shingle
+    pub shingle_words: usize,
+    pub bands: usize,
+    /// MinHash values per band
+    pub rows: usize,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            near_duplicates: NearDuplicateMode::Off,
+            threshold: 0.8,
+            shingle_words: 3,
+            bands: 16,
+            rows: 8,
+        }
+    }
+}
+
+/// Pair of live texts found to be near-duplicates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NearDuplicate {
+    /// Earlier text of the pair
+    pub text: usize,
+    /// Later text, nearly identical to `text`
+    pub duplicate: usize,
+    /// Estimated Jaccard similarity of their shingles
+    pub similarity: f32,
+}
+
+/// MinHash signatures of texts, one value per band row
+#[derive(Debug, Clone)]
+pub(crate) struct MinHasher {
+    seeds: Vec<u64>,
+    shingle_words: usize,
+}
+
+impl MinHasher {
+    pub(crate) fn new(config: &DedupConfig) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        Self {
+            seeds: (0..config.bands * config.rows).map(|_| rng.next_u64()).collect(),
+            shingle_words: config.shingle_words.max(1),
+        }
+    }
+
+    /// Signature of `text`, or `None` if it has no words
+    pub(crate) fn signature(&self, text: &str) -> Option<Vec<u64>> {
+        let tokens = lexical_tokens(text);
+        if tokens.is_empty() {
+            return None;
+        }
+        let shingles: Vec<u64> = tokens
+            .windows(self.shingle_words.min(tokens.len()))
+            .map(|shingle| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                shingle.hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect();
+        Some(
+            self.seeds
+                .iter()
+                .map(|&seed| shingles.iter().map(|&h| mix(h ^ seed)).min().unwrap_or(u64::MAX))
+                .collect(),
+        )
+    }
+}
+
+/// SplitMix64 finalizer, turning each seed into an independent hash function
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// LSH buckets over MinHash signatures, keyed by text id
+///
+/// The store keeps one over its live texts, updated as texts are added,
+/// removed and renumbered, so no text is signed twice. Derived from the
+/// texts, so it is rebuilt on load rather than persisted.
+#[derive(Debug, Clone)]
+pub(crate) struct LshIndex {
+    hasher: MinHasher,
+    bands: usize,
+    rows: usize,
+    threshold: f32,
+    /// `(band, hash of the band's rows)` -> ids
+    buckets: HashMap<(usize, u64), Vec<usize>>,
+    signatures: HashMap<usize, Vec<u64>>,
+}
+
+impl LshIndex {
+    pub(crate) fn new(config: &DedupConfig) -> Self {
+        Self {
+            hasher: MinHasher::new(config),
+            bands: config.bands,
+            rows: config.rows,
+            threshold: config.threshold,
+            buckets: HashMap::new(),
+            signatures: HashMap::new(),
+        }
+    }
+
+    /// Signature of `text` under this index's hash functions, or `None` if it has no words
+    pub(crate) fn signature(&self, text: &str) -> Option<Vec<u64>> {
+        self.hasher.signature(text)
+    }
+
+    pub(crate) fn insert(&mut self, id: usize, signature: Vec<u64>) {
+        for key in self.band_keys(&signature) {
+            self.buckets.entry(key).or_default().push(id);
+        }
+        self.signatures.insert(id, signature);
+    }
+
+    /// Move every entry of `other` into this index
+    pub(crate) fn append(&mut self, other: LshIndex) {
+        for (id, signature) in other.signatures {
+            self.insert(id, signature);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: usize) {
+        let Some(signature) = self.signatures.remove(&id) else {
+            return;
+        };
+        for key in self.band_keys(&signature) {
+            if let Some(ids) = self.buckets.get_mut(&key) {
+                ids.retain(|&other| other != id);
+                if ids.is_empty() {
+                    self.buckets.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// The same signatures under new ids, dropping those mapped to `None`
+    pub(crate) fn renumber(&self, ids: &[Option<usize>]) -> Self {
+        let mut index = Self {
+            hasher: self.hasher.clone(),
+            buckets: HashMap::new(),
+            signatures: HashMap::new(),
+            ..*self
+        };
+        for (&id, signature) in &self.signatures {
+            if let Some(new_id) = ids[id] {
+                index.insert(new_id, signature.clone());
+            }
+        }
+        index
+    }
+
+    /// Indexed ids at least `threshold` similar to `signature`, most similar first
+    pub(crate) fn near(&self, signature: &[u64]) -> Vec<(usize, f32)> {
+        let mut candidates: Vec<usize> = self
+            .band_keys(signature)
+            .filter_map(|key| self.buckets.get(&key))
+            .flatten()
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let mut near: Vec<(usize, f32)> = candidates
+            .into_iter()
+            .map(|id| (id, similarity(signature, &self.signatures[&id])))
+            .filter(|&(_, similarity)| similarity >= self.threshold)
+            .collect();
+        near.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        near
+    }
+
+    fn band_keys<'a>(&self, signature: &'a [u64]) -> impl Iterator<Item = (usize, u64)> + 'a {
+        signature.chunks(self.rows.max(1)).take(self.bands).enumerate().map(|(band, rows)| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            rows.hash(&mut hasher);
+            (band, hasher.finish())
+        })
+    }
+}
+
+/// Fraction of positions where two signatures agree, estimating Jaccard similarity
+fn similarity(a: &[u64], b: &[u64]) -> f32 {
+    let same = a.iter().zip(b).filter(|(x, y)| x == y).count();
+    same as f32 / a.len().max(1) as f32
+}
+
+impl NumpyGraphStore {
+    /// Every pair of live texts at least `dedup.threshold` similar
+    ///
+    /// Pairs come in order of their later text, then most similar first.
+    /// Uses the graph's `DedupConfig` whatever its `near_duplicates` mode, so
+    /// a graph ingested with `Off` or `Report` can be reviewed afterwards.
+    pub fn near_duplicates(&self) -> Vec<NearDuplicate> {
+        let index = &self.near_index;
+        let mut ids: Vec<usize> = index.signatures.keys().copied().collect();
+        ids.sort_unstable();
+        let mut pairs = Vec::new();
+        for duplicate in ids {
+            pairs.extend(
+                index
+                    .near(&index.signatures[&duplicate])
+                    .into_iter()
+                    .filter(|&(text, _)| text < duplicate)
+                    .map(|(text, similarity)| NearDuplicate {
+                        text,
+                        duplicate,
+                        similarity,
+                    }),
+            );
+        }
+        pairs
+    }
+
+    /// Sign every live text into a fresh `near_index`, e.g. after loading
+    pub(crate) fn rebuild_near_index(&mut self) {
+        let mut index = LshIndex::new(&self.config.dedup);
+        for node in self.texts.iter().filter(|t| !self.removed_texts[t.id]) {
+            if let Some(signature) = index.signature(&node.text) {
+                index.insert(node.id, signature);
+            }
+        }
+        self.near_index = Arc::new(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minhash_similarity() {
+        let config = DedupConfig::default();
+        let hasher = MinHasher::new(&config);
+        let body = "Quarterly revenue grew by twelve percent while operating costs fell across every region, \
+                    driven mostly by lower energy prices and a smaller logistics bill than last year";
+        let a = hasher.signature(body).unwrap();
+        let b = hasher.signature(&format!("{}  \n\nPage 3", body)).unwrap();
+        let c = hasher.signature("Pasta recipes from the north of Italy, with fresh eggs and flour").unwrap();
+        assert_eq!(a, hasher.signature(&body.replace(' ', "\t")).unwrap());
+        assert!(similarity(&a, &b) >= 0.8, "{}", similarity(&a, &b));
+        assert!(similarity(&a, &c) < 0.2);
+        assert!(hasher.signature(" ,.; ").is_none());
+
+        let mut index = LshIndex::new(&config);
+        index.insert(7, a.clone());
+        assert_eq!(index.near(&b).iter().map(|&(id, _)| id).collect::<Vec<_>>(), vec![7]);
+        assert!(index.near(&c).is_empty());
+
+        index.insert(9, c.clone());
+        let mut ids = vec![None; 10];
+        ids[7] = Some(0);
+        ids[9] = Some(1);
+        let mut index = index.renumber(&ids);
+        assert_eq!(index.near(&b).iter().map(|&(id, _)| id).collect::<Vec<_>>(), vec![0]);
+        index.remove(0);
+        assert!(index.near(&a).is_empty());
+        assert_eq!(index.near(&c).iter().map(|&(id, _)| id).collect::<Vec<_>>(), vec![1]);
+    }
+}
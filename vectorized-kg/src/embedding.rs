@@ -0,0 +1,189 @@
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::error::check_vector;
+use crate::KgError;
+
+/// Produces embeddings for texts and keywords
+pub trait Embedder: Send + Sync {
+    /// Embed a batch of texts, returning one vector of `dimension()` floats per input
+    ///
+    /// Failures reach callers of the graph as `KgError::Embedder`.
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>>;
+
+    /// Length of every vector returned by `embed`
+    fn dimension(&self) -> usize;
+
+    /// Identifier of the underlying model, recorded with the graph
+    fn model_id(&self) -> &str;
+}
+
+impl std::fmt::Debug for dyn Embedder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Embedder")
+            .field("model_id", &self.model_id())
+            .field("dimension", &self.dimension())
+            .finish()
+    }
+}
+
+/// Deterministic embedder that seeds a ChaCha8 RNG with the hash of each text.
+///
+/// Vectors carry no semantic meaning; intended for tests and demos.
+#[derive(Debug, Clone)]
+pub struct HashEmbedder {
+    dim: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dim: usize) -> Self {
+        Self { dim }
+    }
+}
+
+impl Embedder for HashEmbedder {
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        // Deterministic embeddings based on text hash
+        Ok(texts
+            .iter()
+            .map(|text| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                text.hash(&mut hasher);
+                let seed = hasher.finish();
+                let mut local_rng = ChaCha8Rng::seed_from_u64(seed);
+                let mut vec = vec![0.0f32; self.dim];
+                for v in vec.iter_mut() {
+                    *v = (local_rng.next_u32() as f32) / (u32::MAX as f32) * 2.0 - 1.0;
+                }
+                // Normalize
+                let norm: f32 = vec.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    vec.iter_mut().for_each(|x| *x /= norm);
+                }
+                vec
+            })
+            .collect())
+    }
+
+    fn dimension(&self) -> usize {
+        self.dim
+    }
+
+    fn model_id(&self) -> &str {
+        "hash-chacha8"
+    }
+}
+
+/// Embedder that runs an external program for every batch
+///
+/// The program reads the texts as a JSON array of strings on standard input
+/// and writes a JSON array of vectors, one per text and in the same order,
+/// to standard output. Any model can be wrapped this way, e.g. with a short
+/// Python script around sentence-transformers. The command line is the
+/// model id recorded with the graph.
+#[derive(Debug, Clone)]
+pub struct CommandEmbedder {
+    program: String,
+    args: Vec<String>,
+    dim: usize,
+    model_id: String,
+}
+
+impl CommandEmbedder {
+    pub fn new(program: impl Into<String>, args: Vec<String>, dim: usize) -> Self {
+        let program = program.into();
+        let command: Vec<&str> = std::iter::once(program.as_str()).chain(args.iter().map(String::as_str)).collect();
+        let model_id = format!("command:{}", command.join(" "));
+        Self {
+            program,
+            args,
+            dim,
+            model_id,
+        }
+    }
+}
+
+impl Embedder for CommandEmbedder {
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        // Batches without new keywords are common; no need to start the program
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let input = serde_json::to_vec(texts)?;
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot run {}: {}", self.program, e))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // Written from another thread so a program that answers while still
+        // reading cannot block on a full stdout pipe
+        let (written, output) = std::thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(&input));
+            let output = child.wait_with_output();
+            (writer.join().expect("writing to a pipe does not panic"), output)
+        });
+        let output = output?;
+        if !output.status.success() {
+            return Err(format!("{} exited with {}", self.program, output.status).into());
+        }
+        written?;
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dim
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+}
+
+/// Embed `texts` and verify the embedder honoured its reported dimension
+/// and returned finite values
+pub(crate) fn embed_checked(embedder: &dyn Embedder, texts: &[String]) -> Result<Vec<Vec<f32>>, KgError> {
+    let vectors = embedder
+        .embed(texts)
+        .map_err(|e| KgError::Embedder(format!("{} failed: {}", embedder.model_id(), e)))?;
+    if vectors.len() != texts.len() {
+        return Err(KgError::Embedder(format!(
+            "{} returned {} vectors for {} inputs",
+            embedder.model_id(),
+            vectors.len(),
+            texts.len()
+        )));
+    }
+    let what = format!("embedding from {}", embedder.model_id());
+    for vector in &vectors {
+        check_vector(vector, embedder.dimension(), &what)?;
+    }
+    Ok(vectors)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_embedder() {
+        let texts = vec!["solar panels".to_string(), "trains".to_string()];
+        let script = "cat > /dev/null; echo '[[1, 0], [0, 1]]'";
+        let embedder = CommandEmbedder::new("sh", vec!["-c".to_string(), script.to_string()], 2);
+        assert_eq!(embedder.model_id(), format!("command:sh -c {}", script));
+        assert_eq!(embed_checked(&embedder, &texts).unwrap(), vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        let short = CommandEmbedder::new("sh", vec!["-c".to_string(), script.to_string()], 3);
+        assert!(matches!(embed_checked(&short, &texts), Err(KgError::DimensionMismatch { .. })));
+        let failing = CommandEmbedder::new("sh", vec!["-c".to_string(), "exit 3".to_string()], 2);
+        assert!(matches!(embed_checked(&failing, &texts), Err(KgError::Embedder(_))));
+        let missing = CommandEmbedder::new("/nonexistent/embedder", Vec::new(), 2);
+        assert!(matches!(embed_checked(&missing, &texts), Err(KgError::Embedder(_))));
+        // Nothing to embed, so the program is never started
+        assert!(missing.embed(&[]).unwrap().is_empty());
+    }
+}
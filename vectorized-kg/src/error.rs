@@ -0,0 +1,86 @@
+use std::fmt;
+
+/// Errors returned by the library
+#[derive(Debug)]
+pub enum KgError {
+    /// A vector's length differs from the dimension it is used with
+    DimensionMismatch { expected: usize, found: usize },
+    /// An embedding or query vector contains NaN or infinity
+    NanEmbedding(String),
+    /// A text or keyword id beyond the end of the graph
+    IndexOutOfRange { kind: NodeKind, index: usize, len: usize },
+    /// A snapshot that cannot be decoded or whose parts disagree
+    CorruptSnapshot(String),
+    /// A snapshot or embedding file written with a layout version this build
+    /// does not know; `supported` is the newest one it reads
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// The graph has no embedder to embed new texts or queries with
+    MissingEmbedder,
+    /// The embedder failed, broke its contract or is not the graph's model
+    Embedder(String),
+    /// Input that cannot be turned into documents or a snapshot
+    InvalidInput(String),
+    Io(std::io::Error),
+}
+
+/// Kind of node an id refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Text,
+    Keyword,
+}
+
+impl fmt::Display for KgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KgError::DimensionMismatch { expected, found } => {
+                write!(f, "embedding dimension mismatch: expected {}, found {}", expected, found)
+            }
+            KgError::NanEmbedding(what) => write!(f, "non-finite value in {}", what),
+            KgError::IndexOutOfRange { kind, index, len } => {
+                let kind = match kind {
+                    NodeKind::Text => "text",
+                    NodeKind::Keyword => "keyword",
+                };
+                write!(f, "{} id {} out of range (graph has {})", kind, index, len)
+            }
+            KgError::CorruptSnapshot(what) => write!(f, "corrupt snapshot: {}", what),
+            KgError::UnsupportedVersion { found, supported } => {
+                write!(f, "unsupported snapshot version {} (this build reads up to {})", found, supported)
+            }
+            KgError::MissingEmbedder => write!(f, "graph has no embedder; attach one with set_embedder"),
+            KgError::Embedder(what) => write!(f, "embedder error: {}", what),
+            KgError::InvalidInput(what) => write!(f, "{}", what),
+            KgError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for KgError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KgError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for KgError {
+    fn from(e: std::io::Error) -> Self {
+        KgError::Io(e)
+    }
+}
+
+/// Check that `vector` has `dim` finite values; `what` names it in the error
+pub(crate) fn check_vector(vector: &[f32], dim: usize, what: &str) -> Result<(), KgError> {
+    if vector.len() != dim {
+        return Err(KgError::DimensionMismatch {
+            expected: dim,
+            found: vector.len(),
+        });
+    }
+    if !vector.iter().all(|x| x.is_finite()) {
+        return Err(KgError::NanEmbedding(what.to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,342 @@
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::chunker::token_spans;
+use crate::stopwords;
+
+/// TextRank damping factor
+const TEXT_RANK_DAMPING: f32 = 0.85;
+
+/// TextRank power iterations; scores settle well before this on text-sized graphs
+const TEXT_RANK_ITERATIONS: usize = 30;
+
+/// Language of the stopword list and stemmer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    English,
+    French,
+    German,
+    Spanish,
+}
+
+impl Language {
+    fn stopwords(self) -> &'static [&'static str] {
+        match self {
+            Language::English => stopwords::ENGLISH,
+            Language::French => stopwords::FRENCH,
+            Language::German => stopwords::GERMAN,
+            Language::Spanish => stopwords::SPANISH,
+        }
+    }
+
+    fn algorithm(self) -> Algorithm {
+        match self {
+            Language::English => Algorithm::English,
+            Language::French => Algorithm::French,
+            Language::German => Algorithm::German,
+            Language::Spanish => Algorithm::Spanish,
+        }
+    }
+}
+
+/// How multi-word keyphrases are found and how candidates are ranked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyphraseMethod {
+    /// Single words only, ranked by frequency in the text
+    None,
+    /// RAKE: words scored by co-occurrence degree over frequency, phrases by the sum of their words
+    Rake,
+    /// TextRank: words scored by PageRank over adjacent-word links, phrases by the sum of their words
+    TextRank,
+}
+
+/// Options for turning texts into keywords
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeywordConfig {
+    /// Selects the stopword list and stemmer
+    pub language: Language,
+    /// Drop the language's stopwords
+    pub stopwords: bool,
+    /// Further words to drop, matched case-insensitively
+    pub extra_stopwords: Vec<String>,
+    /// Reduce words to their stem, so "panels" and "panel" are one keyword
+    pub stemming: bool,
+    /// Shortest keyword word, in characters
+    pub min_chars: usize,
+    pub keyphrases: KeyphraseMethod,
+    /// Longest keyphrase, in words
+    pub max_phrase_words: usize,
+    /// Keep only the best-ranked keywords of each text
+    pub max_keywords_per_text: Option<usize>,
+}
+
+impl Default for KeywordConfig {
+    fn default() -> Self {
+        Self {
+            language: Language::English,
+            stopwords: true,
+            extra_stopwords: Vec::new(),
+            stemming: false,
+            min_chars: 4,
+            keyphrases: KeyphraseMethod::None,
+            max_phrase_words: 3,
+            max_keywords_per_text: None,
+        }
+    }
+}
+
+/// Keyword pipeline built from a `KeywordConfig`
+///
+/// Text is split into Unicode words; stopwords, numbers and words shorter
+/// than `min_chars` break it into candidate phrases. Keywords are the
+/// lowercased (and optionally stemmed) candidate words, plus candidate
+/// phrases of up to `max_phrase_words` words when a keyphrase method is set.
+pub struct KeywordExtractor {
+    config: KeywordConfig,
+    stopwords: HashSet<String>,
+    stemmer: Option<Stemmer>,
+}
+
+impl std::fmt::Debug for KeywordExtractor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeywordExtractor")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A keyword of one text while it is being ranked
+struct Candidate {
+    text: String,
+    words: Vec<usize>,
+    count: usize,
+}
+
+impl KeywordExtractor {
+    pub fn new(config: &KeywordConfig) -> Self {
+        let mut stopwords: HashSet<String> = config.extra_stopwords.iter().map(|w| w.to_lowercase()).collect();
+        if config.stopwords {
+            stopwords.extend(config.language.stopwords().iter().map(|w| w.to_string()));
+        }
+        Self {
+            config: config.clone(),
+            stopwords,
+            stemmer: config.stemming.then(|| Stemmer::create(config.language.algorithm())),
+        }
+    }
+
+    /// Keyword form of a query term, or `None` if no keyword can match it
+    ///
+    /// Applies the same lowercasing, stopword and stemming rules as `extract`.
+    pub fn normalize(&self, term: &str) -> Option<String> {
+        let words: Option<Vec<String>> = token_spans(term)
+            .into_iter()
+            .map(|(from, to)| &term[from..to])
+            .filter(|token| token.starts_with(char::is_alphanumeric))
+            .map(|token| self.word(token))
+            .collect();
+        words.filter(|words| !words.is_empty()).map(|words| words.join(" "))
+    }
+
+    /// Keywords of `text` with their number of occurrences, best-ranked first
+    ///
+    /// Ties keep the order of first occurrence, so the result is deterministic.
+    pub fn extract(&self, text: &str) -> Vec<(String, usize)> {
+        let words = self.words(text);
+        let runs: Vec<Vec<&str>> = words
+            .split(Option::is_none)
+            .filter(|run| !run.is_empty())
+            .map(|run| run.iter().flatten().map(String::as_str).collect())
+            .collect();
+
+        let mut candidates: Vec<Candidate> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut word_ids: HashMap<&str, usize> = HashMap::new();
+        let mut add = |text: String, words: Vec<usize>| {
+            let id = *index.entry(text.clone()).or_insert_with(|| {
+                candidates.push(Candidate { text, words, count: 0 });
+                candidates.len() - 1
+            });
+            candidates[id].count += 1;
+        };
+        let mut sequence = Vec::new();
+        for run in &runs {
+            let ids: Vec<usize> = run
+                .iter()
+                .map(|&word| {
+                    let next = word_ids.len();
+                    *word_ids.entry(word).or_insert(next)
+                })
+                .collect();
+            for (&word, &id) in run.iter().zip(&ids) {
+                add(word.to_string(), vec![id]);
+            }
+            let is_phrase = (2..=self.config.max_phrase_words).contains(&run.len());
+            if self.config.keyphrases != KeyphraseMethod::None && is_phrase {
+                add(run.join(" "), ids.clone());
+            }
+            sequence.extend(ids);
+        }
+
+        let word_scores = match self.config.keyphrases {
+            KeyphraseMethod::None => {
+                let mut freqs = vec![0.0; word_ids.len()];
+                for &id in &sequence {
+                    freqs[id] += 1.0;
+                }
+                freqs
+            }
+            KeyphraseMethod::Rake => rake_scores(&runs, &word_ids),
+            KeyphraseMethod::TextRank => text_rank_scores(&sequence, word_ids.len()),
+        };
+
+        let mut ranked: Vec<(f32, Candidate)> = candidates
+            .into_iter()
+            .map(|c| (c.words.iter().map(|&w| word_scores[w]).sum(), c))
+            .collect();
+        // Stable sort: equal scores stay in order of first occurrence
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        if let Some(max) = self.config.max_keywords_per_text {
+            ranked.truncate(max);
+        }
+        ranked.into_iter().map(|(_, c)| (c.text, c.count)).collect()
+    }
+
+    /// Normalized words of `text` in order, `None` for tokens that break a phrase
+    fn words(&self, text: &str) -> Vec<Option<String>> {
+        token_spans(text)
+            .into_iter()
+            .map(|(from, to)| &text[from..to])
+            .map(|token| token.starts_with(char::is_alphanumeric).then(|| self.word(token)).flatten())
+            .collect()
+    }
+
+    /// Keyword form of one word token, `None` if it cannot be a keyword
+    fn word(&self, token: &str) -> Option<String> {
+        let word = token.to_lowercase();
+        let too_short = word.chars().count() < self.config.min_chars;
+        if too_short || word.chars().all(char::is_numeric) || self.stopwords.contains(&word) {
+            return None;
+        }
+        Some(match &self.stemmer {
+            Some(stemmer) => stemmer.stem(&word).into_owned(),
+            None => word,
+        })
+    }
+}
+
+/// RAKE word scores: summed length of the phrases a word appears in, over its frequency
+fn rake_scores(runs: &[Vec<&str>], word_ids: &HashMap<&str, usize>) -> Vec<f32> {
+    let mut degrees = vec![0.0; word_ids.len()];
+    let mut freqs = vec![0.0; word_ids.len()];
+    for run in runs {
+        for word in run {
+            degrees[word_ids[word]] += run.len() as f32;
+            freqs[word_ids[word]] += 1.0;
+        }
+    }
+    degrees.iter().zip(&freqs).map(|(degree, freq)| degree / freq).collect()
+}
+
+/// TextRank word scores: PageRank over links between words that follow each other
+fn text_rank_scores(sequence: &[usize], n_words: usize) -> Vec<f32> {
+    let mut neighbours = vec![BTreeSet::new(); n_words];
+    for pair in sequence.windows(2) {
+        if pair[0] != pair[1] {
+            neighbours[pair[0]].insert(pair[1]);
+            neighbours[pair[1]].insert(pair[0]);
+        }
+    }
+    let base = (1.0 - TEXT_RANK_DAMPING) / n_words as f32;
+    let mut ranks = vec![1.0 / n_words as f32; n_words];
+    for _ in 0..TEXT_RANK_ITERATIONS {
+        ranks = neighbours
+            .iter()
+            .map(|links| {
+                let incoming: f32 = links.iter().map(|&j| ranks[j] / neighbours[j].len() as f32).sum();
+                base + TEXT_RANK_DAMPING * incoming
+            })
+            .collect();
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords(config: &KeywordConfig, text: &str) -> Vec<String> {
+        KeywordExtractor::new(config).extract(text).into_iter().map(|(kw, _)| kw).collect()
+    }
+
+    #[test]
+    fn test_tokens_ignore_punctuation_and_stopwords() {
+        let config = KeywordConfig::default();
+        let text = "Hello world. The world, with Ünïcode words from 2024!";
+        assert_eq!(keywords(&config, text), vec!["world", "hello", "ünïcode", "words"]);
+
+        let extractor = KeywordExtractor::new(&config);
+        assert_eq!(extractor.extract("world. world")[0], ("world".to_string(), 2));
+        assert_eq!(extractor.normalize("  World!"), Some("world".to_string()));
+        assert_eq!(extractor.normalize("with"), None);
+
+        let french = KeywordConfig {
+            language: Language::French,
+            extra_stopwords: vec!["Soleil".to_string()],
+            ..KeywordConfig::default()
+        };
+        assert_eq!(keywords(&french, "Le soleil avec des panneaux"), vec!["panneaux"]);
+    }
+
+    #[test]
+    fn test_stemming_merges_word_forms() {
+        let config = KeywordConfig {
+            stemming: true,
+            ..KeywordConfig::default()
+        };
+        let extractor = KeywordExtractor::new(&config);
+        assert_eq!(extractor.extract("panels and panel"), vec![("panel".to_string(), 2)]);
+        assert_eq!(extractor.normalize("Panels"), Some("panel".to_string()));
+    }
+
+    #[test]
+    fn test_keyphrases_rank_above_their_words() {
+        let text = "Solar panels are cheap. Direct sunlight is free, and solar panels like direct sunlight.";
+        for method in [KeyphraseMethod::Rake, KeyphraseMethod::TextRank] {
+            let config = KeywordConfig {
+                keyphrases: method,
+                max_keywords_per_text: Some(2),
+                ..KeywordConfig::default()
+            };
+            let found = KeywordExtractor::new(&config).extract(text);
+            assert_eq!(found.len(), 2, "{:?}", method);
+            assert!(found.iter().all(|(kw, _)| kw.contains(' ')), "{:?}: {:?}", method, found);
+        }
+
+        // Runs longer than max_phrase_words are not phrases
+        let config = KeywordConfig {
+            keyphrases: KeyphraseMethod::Rake,
+            max_phrase_words: 2,
+            ..KeywordConfig::default()
+        };
+        let found = keywords(&config, "solar panels convert sunlight");
+        assert!(found.iter().all(|kw| !kw.contains(' ')));
+    }
+
+    #[test]
+    fn test_extraction_is_deterministic() {
+        let config = KeywordConfig {
+            keyphrases: KeyphraseMethod::TextRank,
+            ..KeywordConfig::default()
+        };
+        let text = "graph nodes link keyword nodes; keyword graph search ranks graph nodes";
+        let first = KeywordExtractor::new(&config).extract(text);
+        for _ in 0..5 {
+            assert_eq!(KeywordExtractor::new(&config).extract(text), first);
+        }
+    }
+}
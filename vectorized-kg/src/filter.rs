@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::ops::RangeInclusive;
+
+use crate::{Metadata, MetadataValue, SourceInfo, TextNode};
+
+/// Predicate over a text's `SourceInfo` and metadata, applied while searching
+///
+/// Searches given a filter only consider matching texts, so they still
+/// return `k` hits when at least `k` texts match. Page, chunk and metadata
+/// ranges are inclusive and never match texts without that field. A text
+/// ingested from several places matches when one of its sources, with the
+/// metadata ingested there, satisfies the whole filter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    Filename(String),
+    FilenameIn(Vec<String>),
+    FileType(String),
+    Pages { min: Option<u32>, max: Option<u32> },
+    Chunks { min: Option<usize>, max: Option<usize> },
+    Tag(String),
+    /// Metadata `key` is present
+    HasMetadata(String),
+    /// Metadata `key` equals `value`, or is a list containing the text `value`
+    Metadata { key: String, value: MetadataValue },
+    /// Metadata `key` lies within the bounds, compared as by `MetadataValue::compare`
+    MetadataRange {
+        key: String,
+        min: Option<MetadataValue>,
+        max: Option<MetadataValue>,
+    },
+    /// Every filter matches; an empty list matches everything
+    All(Vec<Filter>),
+    /// Some filter matches; an empty list matches nothing
+    Any(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn filename(filename: impl Into<String>) -> Self {
+        Filter::Filename(filename.into())
+    }
+
+    pub fn filename_in<S: Into<String>>(filenames: impl IntoIterator<Item = S>) -> Self {
+        Filter::FilenameIn(filenames.into_iter().map(Into::into).collect())
+    }
+
+    pub fn file_type(file_type: impl Into<String>) -> Self {
+        Filter::FileType(file_type.into())
+    }
+
+    pub fn pages(pages: RangeInclusive<u32>) -> Self {
+        Filter::Pages {
+            min: Some(*pages.start()),
+            max: Some(*pages.end()),
+        }
+    }
+
+    pub fn chunks(chunks: RangeInclusive<usize>) -> Self {
+        Filter::Chunks {
+            min: Some(*chunks.start()),
+            max: Some(*chunks.end()),
+        }
+    }
+
+    pub fn tag(tag: impl Into<String>) -> Self {
+        Filter::Tag(tag.into())
+    }
+
+    pub fn metadata(key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Filter::Metadata {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Both `self` and `other` match
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::All(mut filters) => {
+                filters.push(other);
+                Filter::All(filters)
+            }
+            filter => Filter::All(vec![filter, other]),
+        }
+    }
+
+    /// `self` or `other` matches
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Any(mut filters) => {
+                filters.push(other);
+                Filter::Any(filters)
+            }
+            filter => Filter::Any(vec![filter, other]),
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        Filter::Not(Box::new(self))
+    }
+
+    pub fn matches(&self, node: &TextNode) -> bool {
+        node.locations().any(|(source, metadata)| self.matches_at(source, metadata))
+    }
+
+    /// Whether a text located at `source` with `metadata` matches
+    pub fn matches_at(&self, source: &SourceInfo, metadata: &Metadata) -> bool {
+        match self {
+            Filter::Filename(filename) => &source.filename == filename,
+            Filter::FilenameIn(filenames) => filenames.contains(&source.filename),
+            Filter::FileType(file_type) => &source.file_type == file_type,
+            Filter::Pages { min, max } => source.page_num.is_some_and(|page| within(page, *min, *max)),
+            Filter::Chunks { min, max } => source.chunk_idx.is_some_and(|chunk| within(chunk, *min, *max)),
+            Filter::Tag(tag) => source.tags.contains(tag),
+            Filter::HasMetadata(key) => metadata.contains_key(key),
+            Filter::Metadata { key, value } => metadata.get(key).is_some_and(|stored| stored.matches(value)),
+            Filter::MetadataRange { key, min, max } => metadata.get(key).is_some_and(|stored| {
+                let above = min.as_ref().is_none_or(|min| stored.compare(min).is_some_and(Ordering::is_ge));
+                let below = max.as_ref().is_none_or(|max| stored.compare(max).is_some_and(Ordering::is_le));
+                above && below
+            }),
+            Filter::All(filters) => filters.iter().all(|f| f.matches_at(source, metadata)),
+            Filter::Any(filters) => filters.iter().any(|f| f.matches_at(source, metadata)),
+            Filter::Not(filter) => !filter.matches_at(source, metadata),
+        }
+    }
+}
+
+fn within<T: PartialOrd>(value: T, min: Option<T>, max: Option<T>) -> bool {
+    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+
+    fn node(filename: &str, page_num: Option<u32>, chunk_idx: Option<usize>, tags: &[&str]) -> TextNode {
+        TextNode {
+            id: 0,
+            text: String::new(),
+            source: SourceInfo {
+                filename: filename.to_string(),
+                page_num,
+                file_type: "txt".to_string(),
+                chunk_idx,
+                char_range: None,
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+            },
+            token_count: 0,
+            metadata: Metadata::new(),
+            other_sources: Vec::new(),
+            other_metadata: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let report = node("report.txt", Some(3), Some(7), &["finance"]);
+        let notes = node("notes.txt", None, Some(0), &[]);
+
+        assert!(Filter::filename("report.txt").matches(&report));
+        assert!(Filter::filename_in(["a.txt", "notes.txt"]).matches(&notes));
+        assert!(Filter::file_type("txt").matches(&notes));
+        assert!(Filter::pages(1..=3).matches(&report));
+        assert!(!Filter::pages(4..=9).matches(&report));
+        assert!(!Filter::pages(0..=9).matches(&notes));
+        assert!(Filter::Pages { min: Some(3), max: None }.matches(&report));
+        assert!(Filter::chunks(0..=0).matches(&notes));
+        assert!(Filter::tag("finance").matches(&report));
+        assert!(!Filter::tag("finance").matches(&notes));
+
+        let finance_pages = Filter::tag("finance").and(Filter::pages(1..=5));
+        assert!(finance_pages.matches(&report) && !finance_pages.matches(&notes));
+        let either = Filter::filename("notes.txt").or(Filter::tag("finance"));
+        assert!(either.matches(&report) && either.matches(&notes));
+        assert!(Filter::filename("notes.txt").negate().matches(&report));
+        assert!(Filter::All(Vec::new()).matches(&notes));
+        assert!(!Filter::Any(Vec::new()).matches(&notes));
+
+        // One of the sources has to satisfy the whole filter
+        let mut merged = report.clone();
+        merged.other_sources.push(notes.source.clone());
+        assert!(Filter::filename("notes.txt").matches(&merged));
+        assert!(!Filter::filename("notes.txt").and(Filter::tag("finance")).matches(&merged));
+        assert!(Filter::filename("notes.txt").negate().matches(&merged));
+    }
+
+    #[test]
+    fn test_metadata_filters() {
+        let mut report = node("report.txt", None, None, &[]);
+        report.metadata.insert("author".to_string(), "Ada".into());
+        report.metadata.insert("year".to_string(), MetadataValue::Int(2021));
+        report.metadata.insert("access".to_string(), vec!["staff".to_string(), "finance".to_string()].into());
+
+        assert!(Filter::metadata("author", "Ada").matches(&report));
+        assert!(!Filter::metadata("author", "Bob").matches(&report));
+        assert!(Filter::metadata("access", "finance").matches(&report));
+        assert!(Filter::HasMetadata("year".to_string()).matches(&report));
+        assert!(!Filter::HasMetadata("url".to_string()).matches(&report));
+        let since_2020 = Filter::MetadataRange {
+            key: "year".to_string(),
+            min: Some(MetadataValue::Int(2020)),
+            max: None,
+        };
+        assert!(since_2020.matches(&report));
+        let until_2020 = Filter::MetadataRange {
+            key: "year".to_string(),
+            min: None,
+            max: Some(MetadataValue::Float(2020.5)),
+        };
+        assert!(!until_2020.matches(&report));
+        let text_bound = Filter::MetadataRange {
+            key: "year".to_string(),
+            min: Some("2020".into()),
+            max: None,
+        };
+        assert!(!text_bound.matches(&report));
+
+        // Metadata belongs to the source it was ingested with
+        let mut copy = node("copy.txt", None, None, &[]);
+        copy.metadata.insert("author".to_string(), "Bob".into());
+        let mut merged = report.clone();
+        merged.other_sources.push(copy.source.clone());
+        merged.other_metadata.push(copy.metadata.clone());
+        assert!(Filter::metadata("author", "Bob").matches(&merged));
+        assert!(Filter::metadata("author", "Bob").and(Filter::filename("copy.txt")).matches(&merged));
+        assert!(!Filter::metadata("author", "Bob").and(Filter::filename("report.txt")).matches(&merged));
+        assert!(!Filter::metadata("year", MetadataValue::Int(2021)).and(Filter::filename("copy.txt")).matches(&merged));
+
+        // Sources without their own entry share the node's metadata
+        merged.other_metadata.clear();
+        assert!(Filter::metadata("author", "Ada").and(Filter::filename("copy.txt")).matches(&merged));
+    }
+
+    #[test]
+    fn test_filter_serde() {
+        let filter = Filter::file_type("md").and(Filter::pages(2..=4)).and(Filter::tag("draft").negate());
+        let json = serde_json::to_string(&filter).unwrap();
+        assert!(json.contains("\"file_type\":\"md\""), "{}", json);
+        assert_eq!(serde_json::from_str::<Filter>(&json).unwrap(), filter);
+    }
+}
@@ -0,0 +1,409 @@
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+use crate::search::cosine_distance_unchecked;
+
+/// Indexed collection of equal-length vectors
+pub trait VectorSet {
+    fn len(&self) -> usize;
+
+    fn vector(&self, i: usize) -> &[f32];
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Hierarchical navigable small world graph for approximate cosine search
+///
+/// The index stores only graph links; vectors are read from a `VectorSet` so
+/// they are not duplicated. Node `i` of the index is vector `i` of the set.
+/// Levels are drawn from an RNG seeded by node id, so building the same data
+/// twice gives the same graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HnswIndex {
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+    /// `links[node][level]` are the neighbours of `node` on `level`
+    links: Vec<Vec<Vec<u32>>>,
+    entry_point: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored {
+    dist: f32,
+    id: usize,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist.total_cmp(&other.dist).then(self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl HnswIndex {
+    /// Empty index; `m` is the number of links per node above level 0 (twice that on level 0)
+    pub fn new(m: usize, ef_construction: usize, ef_search: usize) -> Self {
+        Self {
+            m: m.max(2),
+            ef_construction: ef_construction.max(1),
+            ef_search: ef_search.max(1),
+            links: Vec::new(),
+            entry_point: None,
+        }
+    }
+
+    /// Index every vector of `vectors`
+    pub fn build<V: VectorSet + ?Sized>(
+        vectors: &V,
+        m: usize,
+        ef_construction: usize,
+        ef_search: usize,
+    ) -> Self {
+        let mut index = Self::new(m, ef_construction, ef_search);
+        for _ in 0..vectors.len() {
+            index.insert(vectors);
+        }
+        index
+    }
+
+    /// Number of indexed nodes
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    pub fn ef_search(&self) -> usize {
+        self.ef_search
+    }
+
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        self.ef_search = ef_search.max(1);
+    }
+
+    /// Check the invariants of a deserialized index
+    ///
+    /// Every node must have at least level 0, its neighbours on a level must
+    /// exist and reach that level, and the entry point must be a node on the
+    /// top level. Searches and inserts assume this.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.m < 2 || self.ef_construction == 0 || self.ef_search == 0 {
+            return Err(format!(
+                "parameters m={} ef_construction={} ef_search={} are out of range",
+                self.m, self.ef_construction, self.ef_search
+            ));
+        }
+        for (node, levels) in self.links.iter().enumerate() {
+            if levels.is_empty() {
+                return Err(format!("node {} has no levels", node));
+            }
+            for (level, neighbours) in levels.iter().enumerate() {
+                for &neighbour in neighbours {
+                    if self.links.get(neighbour as usize).is_none_or(|n| n.len() <= level) {
+                        return Err(format!("node {} links to {} on level {}", node, neighbour, level));
+                    }
+                }
+            }
+        }
+        let top_level = self.links.iter().map(Vec::len).max();
+        match self.entry_point {
+            None if self.links.is_empty() => Ok(()),
+            Some(entry) if entry < self.links.len() && Some(self.links[entry].len()) == top_level => Ok(()),
+            entry => Err(format!("entry point {:?} is not a top-level node of {}", entry, self.links.len())),
+        }
+    }
+
+    /// Index the next vector of `vectors`, i.e. vector number `self.len()`
+    pub fn insert<V: VectorSet + ?Sized>(&mut self, vectors: &V) {
+        let id = self.links.len();
+        assert!(id < vectors.len(), "vector {} is not in the set", id);
+
+        let level = self.random_level(id);
+        self.links.push(vec![Vec::new(); level + 1]);
+
+        let entry = match self.entry_point {
+            Some(entry) => entry,
+            None => {
+                self.entry_point = Some(id);
+                return;
+            }
+        };
+
+        let query = vectors.vector(id);
+        let top_level = self.links[entry].len() - 1;
+        let mut nearest = vec![self.scored(query, entry, vectors)];
+
+        for lvl in (level + 1..=top_level).rev() {
+            nearest = self.search_layer(query, &nearest, 1, lvl, vectors, &|_| true);
+        }
+
+        for lvl in (0..=level.min(top_level)).rev() {
+            let candidates =
+                self.search_layer(query, &nearest, self.ef_construction, lvl, vectors, &|_| true);
+            let neighbours = self.select_neighbours(&candidates, self.max_links(lvl), vectors);
+            self.links[id][lvl] = neighbours.iter().map(|&n| n as u32).collect();
+
+            for &neighbour in &neighbours {
+                self.links[neighbour][lvl].push(id as u32);
+                if self.links[neighbour][lvl].len() > self.max_links(lvl) {
+                    self.prune(neighbour, lvl, vectors);
+                }
+            }
+            nearest = candidates;
+        }
+
+        if level > top_level {
+            self.entry_point = Some(id);
+        }
+    }
+
+    /// Approximate `k` nearest vectors to `query` as `(id, cosine distance)`, closest first
+    ///
+    /// Only nodes accepted by `filter` are returned, but rejected nodes are still
+    /// traversed so that the graph stays connected.
+    pub fn search<V: VectorSet + ?Sized>(
+        &self,
+        query: &[f32],
+        k: usize,
+        vectors: &V,
+        filter: &dyn Fn(usize) -> bool,
+    ) -> Vec<(usize, f32)> {
+        let entry = match self.entry_point {
+            Some(entry) if k > 0 => entry,
+            _ => return Vec::new(),
+        };
+
+        let mut nearest = vec![self.scored(query, entry, vectors)];
+        for lvl in (1..self.links[entry].len()).rev() {
+            nearest = self.search_layer(query, &nearest, 1, lvl, vectors, &|_| true);
+        }
+
+        let ef = self.ef_search.max(k);
+        let mut found = self.search_layer(query, &nearest, ef, 0, vectors, filter);
+        found.retain(|s| filter(s.id));
+        found.truncate(k);
+        found.into_iter().map(|s| (s.id, s.dist)).collect()
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    fn random_level(&self, id: usize) -> usize {
+        let mut rng = ChaCha8Rng::seed_from_u64(id as u64);
+        let uniform = (rng.next_u32() as f64 + 1.0) / (u32::MAX as f64 + 2.0);
+        let level_mult = 1.0 / (self.m as f64).ln();
+        (-uniform.ln() * level_mult).floor() as usize
+    }
+
+    fn scored<V: VectorSet + ?Sized>(&self, query: &[f32], id: usize, vectors: &V) -> Scored {
+        Scored {
+            dist: cosine_distance_unchecked(query, vectors.vector(id)),
+            id,
+        }
+    }
+
+    /// Greedy best-first search of one level, returning up to `ef` nodes closest first
+    ///
+    /// Nodes rejected by `filter` guide the search but do not fill result slots.
+    fn search_layer<V: VectorSet + ?Sized>(
+        &self,
+        query: &[f32],
+        entries: &[Scored],
+        ef: usize,
+        level: usize,
+        vectors: &V,
+        filter: &dyn Fn(usize) -> bool,
+    ) -> Vec<Scored> {
+        let mut visited: HashSet<usize> = entries.iter().map(|s| s.id).collect();
+        let mut candidates: BinaryHeap<Reverse<Scored>> = entries.iter().copied().map(Reverse).collect();
+        let mut results: BinaryHeap<Scored> = BinaryHeap::new();
+        let mut fallback: BinaryHeap<Scored> = BinaryHeap::new();
+
+        for &entry in entries {
+            if filter(entry.id) {
+                results.push(entry);
+            } else {
+                fallback.push(entry);
+            }
+        }
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            if results.len() >= ef && current.dist > results.peek().unwrap().dist {
+                break;
+            }
+            for &neighbour in &self.links[current.id][level] {
+                let neighbour = neighbour as usize;
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let scored = self.scored(query, neighbour, vectors);
+                if results.len() < ef || scored.dist < results.peek().unwrap().dist {
+                    candidates.push(Reverse(scored));
+                    if filter(neighbour) {
+                        results.push(scored);
+                        if results.len() > ef {
+                            results.pop();
+                        }
+                    } else {
+                        fallback.push(scored);
+                        if fallback.len() > ef {
+                            fallback.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        // Upper levels only need entry points, which need not pass the filter
+        if results.is_empty() {
+            results = fallback;
+        }
+        results.into_sorted_vec()
+    }
+
+    /// Keep candidates that are closer to the new node than to any neighbour
+    /// already kept, then top up with the closest remaining ones
+    fn select_neighbours<V: VectorSet + ?Sized>(
+        &self,
+        candidates: &[Scored],
+        max_links: usize,
+        vectors: &V,
+    ) -> Vec<usize> {
+        let mut selected: Vec<usize> = Vec::with_capacity(max_links);
+        let mut skipped = Vec::new();
+
+        for candidate in candidates {
+            if selected.len() >= max_links {
+                break;
+            }
+            let diverse = selected.iter().all(|&s| {
+                cosine_distance_unchecked(vectors.vector(candidate.id), vectors.vector(s)) > candidate.dist
+            });
+            if diverse {
+                selected.push(candidate.id);
+            } else {
+                skipped.push(candidate.id);
+            }
+        }
+
+        for id in skipped {
+            if selected.len() >= max_links {
+                break;
+            }
+            selected.push(id);
+        }
+        selected
+    }
+
+    fn prune<V: VectorSet + ?Sized>(&mut self, node: usize, level: usize, vectors: &V) {
+        let base = vectors.vector(node);
+        let mut candidates: Vec<Scored> = self.links[node][level]
+            .iter()
+            .map(|&n| self.scored(base, n as usize, vectors))
+            .collect();
+        candidates.sort();
+        let kept = self.select_neighbours(&candidates, self.max_links(level), vectors);
+        self.links[node][level] = kept.into_iter().map(|n| n as u32).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Vectors(Vec<Vec<f32>>);
+
+    impl VectorSet for Vectors {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn vector(&self, i: usize) -> &[f32] {
+            &self.0[i]
+        }
+    }
+
+    fn random_vectors(n: usize, dim: usize) -> Vectors {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        Vectors(
+            (0..n)
+                .map(|_| {
+                    (0..dim)
+                        .map(|_| rng.next_u32() as f32 / u32::MAX as f32 * 2.0 - 1.0)
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    fn exact(vectors: &Vectors, query: &[f32], k: usize) -> Vec<usize> {
+        let mut all: Vec<(usize, f32)> = (0..vectors.len())
+            .map(|i| (i, cosine_distance_unchecked(query, vectors.vector(i))))
+            .collect();
+        all.sort_by(|a, b| a.1.total_cmp(&b.1));
+        all.into_iter().take(k).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn test_recall_against_exact() {
+        let vectors = random_vectors(1000, 16);
+        let index = HnswIndex::build(&vectors, 16, 100, 64);
+        let queries = random_vectors(50, 16);
+
+        let mut hits = 0;
+        for query in &queries.0 {
+            let approx: HashSet<usize> = index
+                .search(query, 10, &vectors, &|_| true)
+                .into_iter()
+                .map(|(i, _)| i)
+                .collect();
+            hits += exact(&vectors, query, 10)
+                .into_iter()
+                .filter(|i| approx.contains(i))
+                .count();
+        }
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        assert!(recall > 0.9, "recall {}", recall);
+    }
+
+    #[test]
+    fn test_filtered_search() {
+        let vectors = random_vectors(300, 8);
+        let index = HnswIndex::build(&vectors, 8, 64, 32);
+        let results = index.search(vectors.vector(0), 10, &vectors, &|i| i % 2 == 1);
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|&(i, _)| i % 2 == 1));
+        for pair in results.windows(2) {
+            assert!(pair[0].1 <= pair[1].1);
+        }
+    }
+
+    #[test]
+    fn test_deterministic_build() {
+        let vectors = random_vectors(200, 8);
+        let a = HnswIndex::build(&vectors, 8, 32, 16);
+        let b = HnswIndex::build(&vectors, 8, 32, 16);
+        assert_eq!(a.links, b.links);
+        assert_eq!(a.entry_point, b.entry_point);
+    }
+}
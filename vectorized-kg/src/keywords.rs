@@ -0,0 +1,607 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::{CsrMatrix, GraphConfig, KeywordExtractor, KgError, NumpyGraphStore};
+
+/// Marks "no path" in `NumpyGraphStore::pred_mat`
+pub(crate) const NO_PREDECESSOR: u32 = u32::MAX;
+
+/// Path between two keywords in the keyword graph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeywordPath {
+    /// Keyword ids from start to end, inclusive
+    pub keywords: Vec<usize>,
+    /// For each hop `keywords[i] -> keywords[i + 1]`, the texts containing both
+    /// keywords (empty for pure embedding-similarity edges)
+    pub texts: Vec<Vec<usize>>,
+}
+
+/// Keyword postings and pairwise co-occurrence counts over live texts
+///
+/// Kept in step with `tf_mat` and the text tombstones as texts are added and
+/// removed, so BM25 weights can be computed on demand and a keyword's edges
+/// rescored without rescanning every text. Derived from `tf_mat`, so it is
+/// rebuilt on load rather than persisted.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cooccurrence {
+    /// Live texts counted
+    texts: usize,
+    /// Tokens in the live texts counted
+    tokens: usize,
+    /// Live texts containing each keyword, in id order, with its term frequency
+    postings: Vec<Vec<(usize, f32)>>,
+    /// For each keyword, every other keyword sharing a live text -> texts containing both
+    pairs: Vec<HashMap<usize, u32>>,
+}
+
+impl Cooccurrence {
+    /// Count text `text`, with `tokens` tokens and keyword row `row` of `tf_mat`
+    ///
+    /// Texts must be counted in increasing id order.
+    pub(crate) fn add(&mut self, text: usize, (keywords, tfs): (&[usize], &[f32]), tokens: usize) {
+        self.texts += 1;
+        self.tokens += tokens;
+        if let Some(&last) = keywords.iter().max() {
+            if self.postings.len() <= last {
+                self.postings.resize(last + 1, Vec::new());
+                self.pairs.resize(last + 1, HashMap::new());
+            }
+        }
+        for (&x, &tf) in keywords.iter().zip(tfs) {
+            self.postings[x].push((text, tf));
+            for &y in keywords.iter().filter(|&&y| y != x) {
+                *self.pairs[x].entry(y).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Uncount a text previously counted with `add`
+    pub(crate) fn remove(&mut self, text: usize, (keywords, _): (&[usize], &[f32]), tokens: usize) {
+        self.texts -= 1;
+        self.tokens -= tokens;
+        for &x in keywords {
+            if let Ok(at) = self.postings[x].binary_search_by_key(&text, |&(i, _)| i) {
+                self.postings[x].remove(at);
+            }
+            for &y in keywords.iter().filter(|&&y| y != x) {
+                if let Some(count) = self.pairs[x].get_mut(&y) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.pairs[x].remove(&y);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Live texts containing `keyword`
+    pub(crate) fn doc_freq(&self, keyword: usize) -> usize {
+        self.postings(keyword).len()
+    }
+
+    /// Live texts containing `keyword` with its term frequency, in id order
+    fn postings(&self, keyword: usize) -> &[(usize, f32)] {
+        self.postings.get(keyword).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Edges of the keyword graph, held per keyword so rows can be rescored one at a time
+///
+/// Each keyword keeps its `k_neighbors` strongest edges. An edge exists if
+/// either endpoint keeps it, so the graph is symmetric, and weighs the more
+/// either end gave it. A row keeps the weights it was scored with until its
+/// keyword's texts or similarity links change.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct KeywordEdges {
+    /// Strongest edges of each keyword as `(keyword, weight)`, strongest first
+    pub(crate) kept: Vec<Vec<(usize, f32)>>,
+    /// Embedding-similarity links as `(keyword, similarity)`, found when the
+    /// later of the two keywords was added
+    similar: Vec<Vec<(usize, f32)>>,
+    /// Keywords whose `kept` row holds each keyword; derived from `kept`
+    #[serde(skip)]
+    kept_by: Vec<Vec<usize>>,
+}
+
+impl KeywordEdges {
+    /// Rows for keywords `0..n_keywords`
+    fn resize(&mut self, n_keywords: usize) {
+        self.kept.resize(n_keywords, Vec::new());
+        self.similar.resize(n_keywords, Vec::new());
+        self.kept_by.resize(n_keywords, Vec::new());
+    }
+
+    /// Replace the kept edges of `x`
+    fn set_kept(&mut self, x: usize, row: Vec<(usize, f32)>) {
+        for &(y, _) in &self.kept[x] {
+            self.kept_by[y].retain(|&z| z != x);
+        }
+        for &(y, _) in &row {
+            self.kept_by[y].push(x);
+        }
+        self.kept[x] = row;
+    }
+
+    /// Link `x` and `y` by embedding similarity, unless they already are
+    fn link_similar(&mut self, x: usize, y: usize, similarity: f32) {
+        if !self.similar[x].iter().any(|&(z, _)| z == y) {
+            self.similar[x].push((y, similarity));
+            self.similar[y].push((x, similarity));
+        }
+    }
+
+    /// Every edge of `x`, with repeats when both ends keep it
+    fn edges(&self, x: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let kept_by = self.kept_by[x].iter().filter_map(move |&y| {
+            self.kept[y].iter().find(|&&(z, _)| z == x).map(|&(_, weight)| (y, weight))
+        });
+        self.kept[x].iter().copied().chain(kept_by)
+    }
+
+    /// Fill in `kept_by` after deserializing
+    pub(crate) fn relink(mut self) -> Self {
+        self.kept_by = vec![Vec::new(); self.kept.len()];
+        for (x, row) in self.kept.iter().enumerate() {
+            for &(y, _) in row {
+                self.kept_by[y].push(x);
+            }
+        }
+        self
+    }
+
+    /// The same edges under new keyword ids, dropping those of removed keywords
+    pub(crate) fn renumber(&self, ids: &[Option<usize>]) -> Self {
+        let renumber_rows = |rows: &[Vec<(usize, f32)>]| {
+            rows.iter()
+                .zip(ids)
+                .filter(|(_, id)| id.is_some())
+                .map(|(row, _)| row.iter().filter_map(|&(y, weight)| ids[y].map(|y| (y, weight))).collect())
+                .collect()
+        };
+        KeywordEdges {
+            kept: renumber_rows(&self.kept),
+            similar: renumber_rows(&self.similar),
+            kept_by: Vec::new(),
+        }
+        .relink()
+    }
+
+    /// Check that there is a row per keyword and every edge ends at one
+    pub(crate) fn check(&self, n_keywords: usize) -> Result<(), String> {
+        for (name, rows) in [("kept", &self.kept), ("similar", &self.similar)] {
+            if rows.len() != n_keywords {
+                return Err(format!("{} rows for {} keywords", rows.len(), n_keywords));
+            }
+            if rows.iter().flatten().any(|&(y, _)| y >= n_keywords) {
+                return Err(format!("{} edge to a keyword that does not exist", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of distinct edges between keywords accepted by `live`
+    pub(crate) fn count(&self, live: impl Fn(usize) -> bool) -> usize {
+        let mut edges = HashSet::new();
+        for (x, row) in self.kept.iter().enumerate().filter(|&(x, _)| live(x)) {
+            for &(y, _) in row.iter().filter(|&&(y, _)| live(y)) {
+                edges.insert((x.min(y), x.max(y)));
+            }
+        }
+        edges.len()
+    }
+}
+
+impl NumpyGraphStore {
+    /// Keywords of `texts` in order of first occurrence, without repeats
+    pub(crate) fn extract_keywords(&self, texts: &[String]) -> Vec<String> {
+        let extractor = KeywordExtractor::new(&self.config.keywords);
+        let mut seen = HashSet::new();
+        texts
+            .iter()
+            .flat_map(|text| extractor.extract(text))
+            .map(|(keyword, _)| keyword)
+            .filter(|keyword| seen.insert(keyword.clone()))
+            .collect()
+    }
+
+    /// Append keyword term-frequency rows for texts from `first_text` onwards
+    pub(crate) fn update_term_frequencies(&mut self, first_text: usize) {
+        let n_keywords = self.keywords.len();
+
+        let extractor = KeywordExtractor::new(&self.config.keywords);
+        let term_freqs: Vec<Vec<(usize, f32)>> = self.texts[first_text..]
+            .iter()
+            .map(|node| {
+                extractor
+                    .extract(&node.text)
+                    .into_iter()
+                    .filter_map(|(keyword, count)| self.keyword_ids.get(&keyword).map(|&id| (id, count as f32)))
+                    .collect()
+            })
+            .collect();
+
+        match &mut self.tf_mat {
+            Some(tf_mat) => Arc::make_mut(tf_mat).append_rows(n_keywords, term_freqs),
+            None => self.tf_mat = Some(Arc::new(CsrMatrix::from_rows(n_keywords, term_freqs))),
+        }
+        if let Some(tf_mat) = &self.tf_mat {
+            let cooccurrence = Arc::make_mut(&mut self.cooccurrence);
+            for i in first_text..tf_mat.nrows() {
+                cooccurrence.add(i, tf_mat.row(i), self.texts[i].token_count);
+            }
+        }
+    }
+
+    /// Recount `cooccurrence` from the live rows of `tf_mat`, e.g. after loading
+    pub(crate) fn recount_cooccurrences(&mut self) {
+        let mut cooccurrence = Cooccurrence::default();
+        if let Some(tf_mat) = &self.tf_mat {
+            for i in (0..tf_mat.nrows()).filter(|&i| !self.removed_texts[i]) {
+                cooccurrence.add(i, tf_mat.row(i), self.texts[i].token_count);
+            }
+        }
+        self.cooccurrence = Arc::new(cooccurrence);
+    }
+
+    /// BM25 weight of a keyword occurring `tf` times in live text `text_idx`
+    ///
+    /// Document frequencies and the average length only count live texts.
+    /// They change with every added or removed text, so weights are computed
+    /// when read rather than stored.
+    fn bm25_weight(&self, text_idx: usize, keyword_idx: usize, tf: f32) -> f32 {
+        let stats = &*self.cooccurrence;
+        let avg_len = stats.tokens as f32 / stats.texts.max(1) as f32;
+        let doc_len = self.texts[text_idx].token_count as f32;
+        let norm = if avg_len > 0.0 { doc_len / avg_len } else { 1.0 };
+        let k1 = self.config.bm25_k1;
+        let b = self.config.bm25_b;
+        bm25_idf(stats.texts, stats.doc_freq(keyword_idx)) * tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * norm))
+    }
+
+    /// Score every keyword's edges from scratch, e.g. after loading a snapshot without them
+    pub(crate) fn build_keyword_edges(&mut self, config: &GraphConfig) {
+        self.keyword_edges = Arc::default();
+        let all: Vec<usize> = (0..self.keywords.len()).collect();
+        self.update_keyword_edges(config, &all, &all, 0);
+    }
+
+    /// Rescore the edges of the keywords whose texts changed
+    ///
+    /// `touched` are the keywords of the texts added or removed, whose
+    /// co-occurrence counts changed. `relinked` are the keywords added,
+    /// removed or restored by the change; keywords linked to them by
+    /// similarity are rescored as well. Keywords from `first_new` onwards are
+    /// new, and only they are searched for similar keywords (when
+    /// `similarity_edges` is set); each link is added to both ends.
+    ///
+    /// Other rows keep the weights they were last scored with. Returns the
+    /// keywords whose neighbours may have changed, for `update_path_predecessors`.
+    pub(crate) fn update_keyword_edges(
+        &mut self,
+        config: &GraphConfig,
+        touched: &[usize],
+        relinked: &[usize],
+        first_new: usize,
+    ) -> Vec<usize> {
+        let n_keywords = self.keywords.len();
+        let mut edges = std::mem::take(&mut self.keyword_edges);
+        let edges_mut = Arc::make_mut(&mut edges);
+        edges_mut.resize(n_keywords);
+
+        if config.similarity_edges {
+            let live = |y: usize| !self.removed_keywords[y];
+            for x in (first_new..n_keywords).filter(|&x| live(x)) {
+                // The index only yields the nearest few; brute force checks every keyword
+                let query = self.keyword_vectors.row(x);
+                let similar = match &self.keyword_index {
+                    Some(index) => index.search(query, config.k_neighbors + 1, &self.keyword_vectors, &live),
+                    None => self.keywords_exact(query, n_keywords),
+                };
+                for (y, dist) in similar {
+                    if y != x && dist <= config.connect_threshold {
+                        edges_mut.link_similar(x, y, 1.0 - dist);
+                    }
+                }
+            }
+        }
+
+        let mut rescore = vec![false; n_keywords];
+        for &x in touched.iter().chain(relinked) {
+            rescore[x] = true;
+        }
+        for &x in relinked {
+            for &(y, _) in &edges_mut.similar[x] {
+                rescore[y] = true;
+            }
+        }
+
+        let mut changed = rescore.clone();
+        for &x in relinked {
+            // Their neighbours gain or lose them without being rescored
+            for (y, _) in edges_mut.edges(x) {
+                changed[y] = true;
+            }
+        }
+        for x in (0..n_keywords).filter(|&x| rescore[x]) {
+            let row = self.strongest_edges(x, &edges_mut.similar[x], config);
+            for &(y, _) in edges_mut.kept[x].iter().chain(&row) {
+                changed[y] = true;
+            }
+            edges_mut.set_kept(x, row);
+        }
+
+        self.keyword_edges = edges;
+        (0..n_keywords).filter(|&x| changed[x]).collect()
+    }
+
+    /// The `k_neighbors` strongest edges of keyword `x`, strongest first
+    ///
+    /// Keywords sharing a live text are scored by normalized PMI; when
+    /// `similarity_edges` is set, keywords linked in `similar` are candidates
+    /// too, weighted by their similarity.
+    fn strongest_edges(&self, x: usize, similar: &[(usize, f32)], config: &GraphConfig) -> Vec<(usize, f32)> {
+        if self.removed_keywords[x] {
+            return Vec::new();
+        }
+        let stats = &*self.cooccurrence;
+        let n_texts = stats.texts as f32;
+        let p_x = stats.doc_freq(x) as f32 / n_texts;
+
+        let mut row: Vec<(usize, f32)> = stats
+            .pairs
+            .get(x)
+            .into_iter()
+            .flatten()
+            .filter_map(|(&y, &count)| {
+                let p_xy = count as f32 / n_texts;
+                let p_y = stats.doc_freq(y) as f32 / n_texts;
+                let npmi = if p_xy >= 1.0 {
+                    1.0
+                } else {
+                    (p_xy / (p_x * p_y)).ln() / -p_xy.ln()
+                };
+                (npmi > 0.0).then_some((y, npmi))
+            })
+            .collect();
+        if config.similarity_edges {
+            row.extend(similar.iter().filter(|&&(y, _)| !self.removed_keywords[y]));
+        }
+
+        // Strongest edge per neighbour
+        row.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        let mut seen = HashSet::new();
+        row.retain(|&(y, _)| seen.insert(y));
+        row.truncate(config.k_neighbors);
+        row
+    }
+
+    /// Precompute bounded-hop BFS predecessors from every keyword
+    ///
+    /// Skipped for graphs above `max_pred_keywords`, where the dense matrix would
+    /// be too large; `keyword_path` then searches on demand.
+    pub(crate) fn build_path_predecessors(&mut self, config: &GraphConfig) {
+        self.pred_mat = None;
+        self.update_path_predecessors(config, &[]);
+    }
+
+    /// Bring `pred_mat` up to date after the keyword graph changed
+    ///
+    /// `changed` are the keywords whose neighbours may have changed. A search
+    /// from a keyword only expands keywords fewer than `max_path_hops` hops
+    /// away, and only in the order of their neighbours, so just the rows of
+    /// keywords that close to a changed one are searched again.
+    pub(crate) fn update_path_predecessors(&mut self, config: &GraphConfig, changed: &[usize]) {
+        let n_keywords = self.keywords.len();
+        if n_keywords == 0 || n_keywords > config.max_pred_keywords {
+            self.pred_mat = None;
+            return;
+        }
+
+        let old = self.pred_mat.take();
+        let n_old = old.as_ref().map_or(0, |old| old.ncols());
+        // Keywords added since have no row to keep
+        let mut stale: Vec<bool> = (0..n_keywords).map(|x| x >= n_old).collect();
+        if config.max_path_hops > 0 {
+            let mut frontier = changed.to_vec();
+            for &x in &frontier {
+                stale[x] = true;
+            }
+            for _ in 1..config.max_path_hops {
+                let mut next = Vec::new();
+                for &x in &frontier {
+                    for (y, _) in self.get_adjacent_keywords(x, usize::MAX) {
+                        if !stale[y] {
+                            stale[y] = true;
+                            next.push(y);
+                        }
+                    }
+                }
+                frontier = next;
+            }
+        }
+
+        let mut pred_mat = Array2::from_elem((n_keywords, n_keywords), NO_PREDECESSOR);
+        for (src, &stale) in stale.iter().enumerate() {
+            if stale {
+                let preds = self.bfs_predecessors(src, config.max_path_hops);
+                pred_mat.row_mut(src).assign(&ndarray::Array1::from(preds));
+            } else if let Some(old) = &old {
+                pred_mat.slice_mut(ndarray::s![src, ..n_old]).assign(&old.row(src));
+            }
+        }
+        self.pred_mat = Some(Arc::new(pred_mat));
+    }
+
+    /// Predecessor of every keyword reachable from `src` within `max_hops` edges
+    ///
+    /// Stronger edges are explored first, so among equally short paths the one
+    /// through heavier edges wins.
+    fn bfs_predecessors(&self, src: usize, max_hops: usize) -> Vec<u32> {
+        let n_keywords = self.keywords.len();
+        let mut preds = vec![NO_PREDECESSOR; n_keywords];
+        let mut visited = vec![false; n_keywords];
+        visited[src] = true;
+
+        let mut frontier = vec![src];
+        for _ in 0..max_hops {
+            let mut next = Vec::new();
+            for &node in &frontier {
+                for (neighbour, _) in self.get_adjacent_keywords(node, usize::MAX) {
+                    if !visited[neighbour] {
+                        visited[neighbour] = true;
+                        preds[neighbour] = node as u32;
+                        next.push(neighbour);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        preds
+    }
+
+    /// Texts with the highest BM25 weight for `keyword_idx`, best first
+    pub fn get_keyword_related_texts(&self, keyword_idx: usize, k: usize) -> Vec<usize> {
+        self.keyword_related_texts_where(keyword_idx, k, &|_| true)
+    }
+
+    /// `get_keyword_related_texts` among the texts accepted by `accept`
+    pub(crate) fn keyword_related_texts_where(
+        &self,
+        keyword_idx: usize,
+        k: usize,
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Vec<usize> {
+        if keyword_idx >= self.keywords.len() || self.removed_keywords[keyword_idx] {
+            return Vec::new();
+        }
+
+        let mut scores: Vec<(usize, f32)> = self
+            .cooccurrence
+            .postings(keyword_idx)
+            .iter()
+            .filter(|&&(i, _)| accept(i))
+            .map(|&(i, tf)| (i, self.bm25_weight(i, keyword_idx, tf)))
+            .collect();
+
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        let k = std::cmp::min(k, scores.len());
+        scores.truncate(k);
+        scores.into_iter().map(|(i, _)| i).collect()
+    }
+
+    /// BM25 association weight between a text and a keyword
+    ///
+    /// Zero if either is removed or the keyword does not occur in the text.
+    pub fn get_association(&self, text_idx: usize, keyword_idx: usize) -> f32 {
+        let tf = match &self.tf_mat {
+            Some(tf_mat) if text_idx < tf_mat.nrows() && keyword_idx < tf_mat.ncols() => {
+                tf_mat.get(text_idx, keyword_idx)
+            }
+            _ => return 0.0,
+        };
+        if tf == 0.0 || self.removed_texts[text_idx] || self.removed_keywords[keyword_idx] {
+            return 0.0;
+        }
+        self.bm25_weight(text_idx, keyword_idx, tf)
+    }
+
+    /// Neighbours of `keyword_idx` with their edge weights, strongest first
+    pub fn get_adjacent_keywords(&self, keyword_idx: usize, k: usize) -> Vec<(usize, f32)> {
+        if keyword_idx >= self.keyword_edges.kept.len() || self.removed_keywords[keyword_idx] {
+            return Vec::new();
+        }
+
+        // An edge kept by both ends weighs the more either gave it
+        let mut weights: HashMap<usize, f32> = HashMap::new();
+        for (y, weight) in self.keyword_edges.edges(keyword_idx) {
+            if !self.removed_keywords[y] {
+                let entry = weights.entry(y).or_insert(weight);
+                *entry = entry.max(weight);
+            }
+        }
+        let mut adjacent: Vec<(usize, f32)> = weights.into_iter().collect();
+
+        adjacent.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        let k = std::cmp::min(k, adjacent.len());
+        adjacent.truncate(k);
+        adjacent
+    }
+
+    /// Chain of keywords linking `from` to `to`, with the texts supporting each hop
+    ///
+    /// Returns `None` if either index is out of range or no path exists within
+    /// `max_path_hops` edges.
+    pub fn keyword_path(&self, from: usize, to: usize) -> Option<KeywordPath> {
+        self.try_keyword_path(from, to).ok().flatten()
+    }
+
+    /// `keyword_path`, failing if either index is out of range
+    pub fn try_keyword_path(&self, from: usize, to: usize) -> Result<Option<KeywordPath>, KgError> {
+        self.keyword(from)?;
+        self.keyword(to)?;
+        Ok(self.path_between(from, to))
+    }
+
+    fn path_between(&self, from: usize, to: usize) -> Option<KeywordPath> {
+        if self.removed_keywords[from] || self.removed_keywords[to] {
+            return None;
+        }
+
+        let preds = match &self.pred_mat {
+            Some(pred_mat) => pred_mat.row(from).to_vec(),
+            None => self.bfs_predecessors(from, self.config.max_path_hops),
+        };
+
+        let mut keywords = vec![to];
+        let mut current = to;
+        while current != from {
+            let pred = preds[current];
+            if pred == NO_PREDECESSOR {
+                return None;
+            }
+            current = pred as usize;
+            keywords.push(current);
+        }
+        keywords.reverse();
+
+        let texts = keywords
+            .windows(2)
+            .map(|hop| self.texts_containing_both(hop[0], hop[1]))
+            .collect();
+
+        Some(KeywordPath { keywords, texts })
+    }
+
+    /// Texts in which both keywords occur, in id order
+    fn texts_containing_both(&self, a: usize, b: usize) -> Vec<usize> {
+        let texts_a = self.cooccurrence.postings(a);
+        let texts_b = self.cooccurrence.postings(b);
+
+        let mut shared = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < texts_a.len() && j < texts_b.len() {
+            match texts_a[i].0.cmp(&texts_b[j].0) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    shared.push(texts_a[i].0);
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        shared
+    }
+}
+
+/// BM25 inverse document frequency, always non-negative
+pub(crate) fn bm25_idf(n_docs: usize, doc_freq: usize) -> f32 {
+    let n = n_docs as f32;
+    let df = doc_freq as f32;
+    (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+}
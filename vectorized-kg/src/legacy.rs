@@ -0,0 +1,321 @@
+//! Binary snapshot layouts written by earlier format versions
+//!
+//! bincode writes a struct as its fields in order, without names or
+//! lengths, so a payload can only be read with the types that wrote it.
+//! Each older layout is mirrored here and upgraded to the current types,
+//! with defaults for the fields it predates. A struct that gained trailing
+//! fields nests its previous layout, which bincode reads exactly like the
+//! same fields written out.
+
+use bincode::Options;
+use ndarray::Array2;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::persistence::GraphStoreSnapshot;
+use crate::{
+    CsrMatrix, EmbeddingMatrix, GraphConfig, HnswIndex, KeywordConfig, KeywordNode, Metadata, SourceInfo, TextNode,
+};
+
+/// Decode a payload written with layout `version`, older than the current one
+pub(crate) fn decode(version: u32, payload: &[u8]) -> Result<GraphStoreSnapshot, bincode::Error> {
+    match version {
+        1 => decode_as::<SnapshotV1>(payload),
+        2 => decode_as::<Snapshot<TextNodeV2<SourceV2>, ConfigV2>>(payload),
+        3 => decode_as::<Snapshot<TextNodeV2<SourceV3>, ConfigV2>>(payload),
+        4 => decode_as::<Snapshot<TextNodeV2<SourceV3>, ConfigV4>>(payload),
+        5 => decode_as::<Snapshot<TextNodeV2<SourceInfo>, ConfigV4>>(payload),
+        6 => decode_as::<Snapshot<TextNodeV6, ConfigV4>>(payload),
+        7 => decode_as::<Snapshot<TextNodeV7, ConfigV4>>(payload),
+        8 => decode_as::<Snapshot<TextNodeV7, GraphConfig>>(payload),
+        9 => decode_as::<Snapshot<TextNode, GraphConfig>>(payload),
+        _ => Err(serde::de::Error::custom(format!("no layout for version {}", version))),
+    }
+}
+
+/// Decode as `T`, requiring it to consume the whole payload so a layout
+/// that does not match the version fails instead of reading garbage
+fn decode_as<T: DeserializeOwned + Into<GraphStoreSnapshot>>(
+    payload: &[u8],
+) -> Result<GraphStoreSnapshot, bincode::Error> {
+    // The options `bincode::serialize` writes with
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes();
+    options.deserialize::<T>(payload).map(Into::into)
+}
+
+/// Versions 2 to 9: embeddings in matrices, nodes and config still growing
+#[derive(Deserialize)]
+struct Snapshot<T, C> {
+    texts: Vec<T>,
+    keywords: Vec<KeywordNode>,
+    text_vectors: Option<EmbeddingMatrix>,
+    keyword_vectors: Option<EmbeddingMatrix>,
+    config: Option<C>,
+    model_id: Option<String>,
+    derived: Derived,
+}
+
+/// Version 1: embeddings stored inside each node
+#[derive(Deserialize)]
+struct SnapshotV1 {
+    texts: Vec<TextNodeV1>,
+    keywords: Vec<KeywordNodeV1>,
+    config: Option<ConfigV2>,
+    model_id: Option<String>,
+    derived: Derived,
+}
+
+/// Trailing fields of versions 1 to 9
+///
+/// The BM25 weights and merged keyword edges they stored are dropped; the
+/// edges are rescored per keyword on load.
+#[derive(Deserialize)]
+struct Derived {
+    tf_mat: Option<CsrMatrix>,
+    #[allow(dead_code)]
+    u_mat: Option<CsrMatrix>,
+    #[allow(dead_code)]
+    adj_mat: Option<CsrMatrix>,
+    pred_mat: Option<Array2<u32>>,
+    removed_texts: Option<Vec<bool>>,
+    removed_keywords: Option<Vec<bool>>,
+    text_index: Option<HnswIndex>,
+    keyword_index: Option<HnswIndex>,
+}
+
+impl Derived {
+    fn into_snapshot(
+        self,
+        texts: Vec<TextNode>,
+        keywords: Vec<KeywordNode>,
+        vectors: (Option<EmbeddingMatrix>, Option<EmbeddingMatrix>),
+        config: Option<GraphConfig>,
+        model_id: Option<String>,
+    ) -> GraphStoreSnapshot {
+        GraphStoreSnapshot {
+            texts,
+            keywords,
+            text_vectors: vectors.0,
+            keyword_vectors: vectors.1,
+            config,
+            model_id,
+            tf_mat: self.tf_mat,
+            keyword_edges: None,
+            pred_mat: self.pred_mat,
+            removed_texts: self.removed_texts,
+            removed_keywords: self.removed_keywords,
+            text_index: self.text_index,
+            keyword_index: self.keyword_index,
+        }
+    }
+}
+
+impl<T: Into<TextNode>, C: Into<GraphConfig>> From<Snapshot<T, C>> for GraphStoreSnapshot {
+    fn from(snapshot: Snapshot<T, C>) -> Self {
+        snapshot.derived.into_snapshot(
+            snapshot.texts.into_iter().map(Into::into).collect(),
+            snapshot.keywords,
+            (snapshot.text_vectors, snapshot.keyword_vectors),
+            snapshot.config.map(Into::into),
+            snapshot.model_id,
+        )
+    }
+}
+
+impl From<SnapshotV1> for GraphStoreSnapshot {
+    fn from(snapshot: SnapshotV1) -> Self {
+        let text_vectors = snapshot.texts.iter().map(|t| t.embedding.clone()).collect();
+        let keyword_vectors = snapshot.keywords.iter().map(|k| k.embedding.clone()).collect();
+        snapshot.derived.into_snapshot(
+            snapshot.texts.into_iter().map(Into::into).collect(),
+            snapshot
+                .keywords
+                .into_iter()
+                .map(|k| KeywordNode { id: k.id, text: k.text })
+                .collect(),
+            (Some(text_vectors), Some(keyword_vectors)),
+            snapshot.config.map(Into::into),
+            snapshot.model_id,
+        )
+    }
+}
+
+/// Versions 1 and 2: no character ranges
+#[derive(Deserialize)]
+struct SourceV2 {
+    filename: String,
+    page_num: Option<u32>,
+    file_type: String,
+    chunk_idx: Option<usize>,
+}
+
+/// Versions 3 and 4: no tags
+#[derive(Deserialize)]
+struct SourceV3 {
+    base: SourceV2,
+    char_range: Option<(usize, usize)>,
+}
+
+impl From<SourceV2> for SourceInfo {
+    fn from(source: SourceV2) -> Self {
+        SourceInfo {
+            filename: source.filename,
+            page_num: source.page_num,
+            file_type: source.file_type,
+            chunk_idx: source.chunk_idx,
+            char_range: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl From<SourceV3> for SourceInfo {
+    fn from(source: SourceV3) -> Self {
+        SourceInfo {
+            char_range: source.char_range,
+            ..source.base.into()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TextNodeV1 {
+    id: usize,
+    text: String,
+    source: SourceV2,
+    embedding: Vec<f32>,
+    token_count: usize,
+}
+
+#[derive(Deserialize)]
+struct KeywordNodeV1 {
+    id: usize,
+    text: String,
+    embedding: Vec<f32>,
+}
+
+/// Versions 2 to 5: no metadata or merged sources
+#[derive(Deserialize)]
+struct TextNodeV2<S> {
+    id: usize,
+    text: String,
+    source: S,
+    token_count: usize,
+}
+
+/// Version 6: no merged sources
+#[derive(Deserialize)]
+struct TextNodeV6 {
+    base: TextNodeV2<SourceInfo>,
+    metadata: Metadata,
+}
+
+/// Versions 7 and 8: merged sources share the node's metadata
+#[derive(Deserialize)]
+struct TextNodeV7 {
+    base: TextNodeV6,
+    other_sources: Vec<SourceInfo>,
+}
+
+impl From<TextNodeV1> for TextNode {
+    fn from(node: TextNodeV1) -> Self {
+        TextNodeV2 {
+            id: node.id,
+            text: node.text,
+            source: node.source,
+            token_count: node.token_count,
+        }
+        .into()
+    }
+}
+
+impl<S: Into<SourceInfo>> From<TextNodeV2<S>> for TextNode {
+    fn from(node: TextNodeV2<S>) -> Self {
+        TextNode {
+            id: node.id,
+            text: node.text,
+            source: node.source.into(),
+            token_count: node.token_count,
+            metadata: Metadata::new(),
+            other_sources: Vec::new(),
+            other_metadata: Vec::new(),
+        }
+    }
+}
+
+impl From<TextNodeV6> for TextNode {
+    fn from(node: TextNodeV6) -> Self {
+        TextNode {
+            metadata: node.metadata,
+            ..node.base.into()
+        }
+    }
+}
+
+impl From<TextNodeV7> for TextNode {
+    fn from(node: TextNodeV7) -> Self {
+        TextNode {
+            other_sources: node.other_sources,
+            ..node.base.into()
+        }
+    }
+}
+
+/// Versions 1 to 3: no keyword or near-duplicate settings
+#[derive(Deserialize)]
+struct ConfigV2 {
+    embedding_dim: usize,
+    k_neighbors: usize,
+    trust_num: usize,
+    negative_multiplier: usize,
+    connect_threshold: f32,
+    bm25_k1: f32,
+    bm25_b: f32,
+    similarity_edges: bool,
+    max_path_hops: usize,
+    max_pred_keywords: usize,
+    use_hnsw: bool,
+    hnsw_m: usize,
+    hnsw_ef_construction: usize,
+    hnsw_ef_search: usize,
+}
+
+impl From<ConfigV2> for GraphConfig {
+    fn from(config: ConfigV2) -> Self {
+        GraphConfig {
+            embedding_dim: config.embedding_dim,
+            k_neighbors: config.k_neighbors,
+            trust_num: config.trust_num,
+            negative_multiplier: config.negative_multiplier,
+            connect_threshold: config.connect_threshold,
+            bm25_k1: config.bm25_k1,
+            bm25_b: config.bm25_b,
+            similarity_edges: config.similarity_edges,
+            max_path_hops: config.max_path_hops,
+            max_pred_keywords: config.max_pred_keywords,
+            use_hnsw: config.use_hnsw,
+            hnsw_m: config.hnsw_m,
+            hnsw_ef_construction: config.hnsw_ef_construction,
+            hnsw_ef_search: config.hnsw_ef_search,
+            ..GraphConfig::default()
+        }
+    }
+}
+
+/// Versions 4 to 7: no near-duplicate settings
+#[derive(Deserialize)]
+struct ConfigV4 {
+    base: ConfigV2,
+    keywords: KeywordConfig,
+}
+
+impl From<ConfigV4> for GraphConfig {
+    fn from(config: ConfigV4) -> Self {
+        GraphConfig {
+            keywords: config.keywords,
+            ..config.base.into()
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::chunker::token_spans;
+use crate::keywords::bm25_idf;
+use crate::search::TextHit;
+use crate::{Filter, KgError, NumpyGraphStore};
+
+/// Inverted index from lowercased word tokens to the texts containing them
+///
+/// Every alphanumeric run is indexed, stopwords and short words included, so
+/// identifiers such as error codes and part numbers match exactly. Derived
+/// from the texts, so it is rebuilt on load rather than persisted.
+///
+/// Postings and lengths are shared between clones; removing a text only
+/// updates the live counters.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LexicalIndex {
+    /// Token -> `(text id, term frequency)`, in increasing text id order
+    postings: Arc<HashMap<String, Vec<(usize, u32)>>>,
+    /// Token count of each indexed text
+    lengths: Arc<Vec<u32>>,
+    /// Number of texts not removed, and their total token count
+    live_docs: usize,
+    live_len: u64,
+}
+
+impl LexicalIndex {
+    pub(crate) fn build<'a>(texts: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut index = Self::default();
+        for text in texts {
+            index.push(text);
+        }
+        index
+    }
+
+    /// Index `text` as the next text id
+    pub(crate) fn push(&mut self, text: &str) {
+        let id = self.lengths.len();
+        let tokens = lexical_tokens(text);
+        Arc::make_mut(&mut self.lengths).push(tokens.len() as u32);
+        self.live_docs += 1;
+        self.live_len += tokens.len() as u64;
+        let mut counts: BTreeMap<String, u32> = BTreeMap::new();
+        for token in tokens {
+            *counts.entry(token).or_insert(0) += 1;
+        }
+        let postings = Arc::make_mut(&mut self.postings);
+        for (token, count) in counts {
+            postings.entry(token).or_default().push((id, count));
+        }
+    }
+
+    /// Stop counting live text `id` in document frequencies and the average length
+    ///
+    /// Its postings stay until the index is rebuilt; searches skip them.
+    pub(crate) fn remove(&mut self, id: usize) {
+        self.live_docs -= 1;
+        self.live_len -= self.lengths[id] as u64;
+    }
+
+    /// BM25 scores of the accepted live texts matching any query token, best first
+    ///
+    /// Document frequencies and the average length count every live text:
+    /// `accept` only decides which of them are ranked, so filtering a search
+    /// does not change the scores of the texts it keeps. `live` must agree
+    /// with the texts passed to `remove`.
+    pub(crate) fn search(
+        &self,
+        query: &str,
+        k: usize,
+        k1: f32,
+        b: f32,
+        live: &dyn Fn(usize) -> bool,
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Vec<(usize, f32)> {
+        if self.live_docs == 0 {
+            return Vec::new();
+        }
+        let n_docs = self.live_docs;
+        let avg_len = self.live_len as f32 / n_docs as f32;
+
+        let mut query_tokens = lexical_tokens(query);
+        query_tokens.sort_unstable();
+        query_tokens.dedup();
+
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for token in &query_tokens {
+            let Some(postings) = self.postings.get(token) else {
+                continue;
+            };
+            let postings: Vec<(usize, u32)> = postings.iter().copied().filter(|&(id, _)| live(id)).collect();
+            let idf = bm25_idf(n_docs, postings.len());
+            for (id, tf) in postings.into_iter().filter(|&(id, _)| accept(id)) {
+                let tf = tf as f32;
+                let norm = if avg_len > 0.0 { self.lengths[id] as f32 / avg_len } else { 1.0 };
+                *scores.entry(id).or_insert(0.0) += idf * tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * norm));
+            }
+        }
+
+        let mut scores: Vec<(usize, f32)> = scores.into_iter().collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scores.truncate(k);
+        scores
+    }
+}
+
+/// Lowercased alphanumeric runs of `text`
+pub(crate) fn lexical_tokens(text: &str) -> Vec<String> {
+    token_spans(text)
+        .into_iter()
+        .map(|(from, to)| &text[from..to])
+        .filter(|token| token.starts_with(char::is_alphanumeric))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// How `hybrid_search` combines its lexical and vector rankings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fusion {
+    /// Weighted reciprocal rank fusion: `weight / (rrf_k + rank)` summed over both rankings
+    Rrf,
+    /// Weighted sum of BM25 scores scaled by the best one and cosine similarities
+    Linear,
+}
+
+/// Options for `hybrid_search`, adjustable per query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HybridConfig {
+    pub fusion: Fusion,
+    /// Weight of the BM25 ranking
+    pub lexical_weight: f32,
+    /// Weight of the embedding ranking
+    pub vector_weight: f32,
+    /// Reciprocal rank fusion constant
+    pub rrf_k: f32,
+    /// Hits taken from each ranking before fusing
+    pub candidates: usize,
+    /// Only rank texts whose source matches
+    pub filter: Option<Filter>,
+}
+
+impl Default for HybridConfig {
+    fn default() -> Self {
+        Self {
+            fusion: Fusion::Rrf,
+            lexical_weight: 1.0,
+            vector_weight: 1.0,
+            rrf_k: 60.0,
+            candidates: 50,
+            filter: None,
+        }
+    }
+}
+
+impl NumpyGraphStore {
+    /// Texts ranked by BM25 over their full text, best first
+    ///
+    /// Uses the graph's `bm25_k1` and `bm25_b`. Needs no embedder.
+    pub fn lexical_search(&self, query: &str, k: usize) -> Vec<(usize, f32)> {
+        self.lexical_search_where(query, k, &|_| true)
+    }
+
+    /// `lexical_search` restricted to texts whose source matches `filter`,
+    /// scored the same as unfiltered
+    pub fn lexical_search_filtered(&self, query: &str, k: usize, filter: &Filter) -> Vec<(usize, f32)> {
+        self.lexical_search_where(query, k, &|i| filter.matches(&self.texts[i]))
+    }
+
+    fn lexical_search_where(&self, query: &str, k: usize, accept: &dyn Fn(usize) -> bool) -> Vec<(usize, f32)> {
+        let (k1, b) = (self.config.bm25_k1, self.config.bm25_b);
+        self.lexical_index
+            .search(query, k, k1, b, &|i| !self.removed_texts[i], accept)
+    }
+
+    /// Texts ranked by fusing `lexical_search` with embedding search
+    ///
+    /// Hit scores are the fused scores. A weight of zero switches a ranking off.
+    pub fn hybrid_search(
+        &self,
+        query: &str,
+        k: usize,
+        config: &HybridConfig,
+    ) -> Result<Vec<TextHit>, KgError> {
+        let accept = |i: usize| config.filter.as_ref().is_none_or(|f| f.matches(&self.texts[i]));
+        let lexical = self.lexical_search_where(query, config.candidates, &accept);
+        let vector: Vec<(usize, f32)> = self
+            .search_texts_where(&self.embed_query(query)?, config.candidates, &accept)
+            .into_iter()
+            .map(|(id, distance)| (id, 1.0 - distance))
+            .collect();
+
+        // Linear fusion scales BM25 by the best score so it is comparable to similarity
+        let best_lexical = lexical.first().map_or(1.0, |&(_, score)| score);
+        let rankings = [
+            (lexical, config.lexical_weight, 1.0 / best_lexical),
+            (vector, config.vector_weight, 1.0),
+        ];
+        let mut fused: BTreeMap<usize, f32> = BTreeMap::new();
+        for (ranking, weight, scale) in rankings {
+            if weight == 0.0 {
+                continue;
+            }
+            for (rank, (id, score)) in ranking.into_iter().enumerate() {
+                let contribution = match config.fusion {
+                    Fusion::Rrf => weight / (config.rrf_k + rank as f32 + 1.0),
+                    Fusion::Linear => weight * score * scale,
+                };
+                *fused.entry(id).or_insert(0.0) += contribution;
+            }
+        }
+
+        let mut fused: Vec<(usize, f32)> = fused.into_iter().collect();
+        fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        fused.truncate(k);
+        Ok(self.text_hits(query, fused, config.filter.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bm25_prefers_rare_exact_tokens() {
+        let mut index = LexicalIndex::default();
+        index.push("Printer shows error E1234 after the update");
+        index.push("Printer shows error E5678");
+        index.push("The update fixed the printer");
+
+        let all = |_| true;
+        let ranked = index.search("e1234 printer", 10, 1.2, 0.75, &all, &all);
+        assert_eq!(ranked[0].0, 0);
+        assert_eq!(ranked.len(), 3);
+        assert!(ranked[0].1 > ranked[1].1);
+
+        // A removed text is neither ranked nor counted in the statistics
+        let mut removed = index.clone();
+        removed.remove(1);
+        let live = |i| i != 1;
+        assert!(removed.search("E5678", 10, 1.2, 0.75, &live, &all).is_empty());
+        let rebuilt = LexicalIndex::build([
+            "Printer shows error E1234 after the update",
+            "The update fixed the printer",
+        ]);
+        let scores = |ranked: Vec<(usize, f32)>| ranked.into_iter().map(|(_, score)| score).collect::<Vec<_>>();
+        assert_eq!(
+            scores(removed.search("update printer", 10, 1.2, 0.75, &live, &all)),
+            scores(rebuilt.search("update printer", 10, 1.2, 0.75, &all, &all))
+        );
+        assert!(index.search("missing", 10, 1.2, 0.75, &all, &all).is_empty());
+
+        // Filtering drops texts from the ranking without rescoring the rest
+        let filtered = index.search("e1234 printer", 10, 1.2, 0.75, &all, &|i| i != 0);
+        assert_eq!(filtered, ranked[1..]);
+    }
+}
@@ -0,0 +1,68 @@
+//! Vectorized knowledge graph of texts and keywords
+//!
+//! `NumpyGraphStore` holds text and keyword nodes with their embeddings, a
+//! BM25 text-keyword matrix and a keyword co-occurrence graph. The modules
+//! split it by concern:
+//!
+//! - `store`: the graph itself, ingestion, removal and compaction
+//! - `embedding`: the `Embedder` trait and the deterministic `HashEmbedder`
+//! - `error`: `KgError`, returned by every fallible operation
+//! - `extraction`: tokenization, stopwords, stemming and keyphrases for keywords
+//! - `keywords`: BM25 weights, the keyword graph and paths
+//! - `chunker`: token-budgeted chunks with overlap and boundary preference
+//! - `dedup`: near-duplicate detection with MinHash and LSH banding
+//! - `loaders`: turning text, Markdown, HTML, CSV and JSONL files into documents
+//! - `search`: nearest-neighbour search over texts and keywords
+//! - `metadata`: typed user metadata carried from documents to text nodes
+//! - `filter`: source and metadata predicates applied during search
+//! - `lexical`: BM25 inverted index over text and hybrid lexical + vector search
+//! - `retrieval`: vector seeds expanded through the keyword graph, with fused ranking
+//! - `persistence`: snapshots on disk, binary, JSON or memory-mapped
+//! - `shared`: `SharedGraph`, consistent snapshots for readers while a writer ingests
+
+pub mod chunker;
+pub mod dedup;
+pub mod embedding;
+pub mod error;
+pub mod extraction;
+pub mod filter;
+mod hnsw;
+pub mod keywords;
+mod legacy;
+pub mod lexical;
+pub mod loaders;
+pub mod metadata;
+pub mod persistence;
+pub mod retrieval;
+pub mod search;
+pub mod shared;
+mod sparse;
+mod stopwords;
+pub mod store;
+mod vectors;
+
+pub use chunker::{chunk_documents, count_tokens, ChunkBoundary, ChunkerConfig};
+pub use dedup::{DedupConfig, NearDuplicate, NearDuplicateMode};
+pub use embedding::{CommandEmbedder, Embedder, HashEmbedder};
+pub use error::{KgError, NodeKind};
+pub use extraction::{KeyphraseMethod, KeywordConfig, KeywordExtractor, Language};
+pub use filter::Filter;
+pub use hnsw::{HnswIndex, VectorSet};
+pub use keywords::KeywordPath;
+pub use lexical::{Fusion, HybridConfig};
+pub use loaders::{load_directory, FileType, LoaderConfig};
+pub use metadata::{Metadata, MetadataValue};
+pub use persistence::SnapshotFormat;
+pub use retrieval::{RetrievalConfig, RetrievalHit, RetrievalPath};
+pub use search::{cosine_distance, KeywordHit, TextHit};
+pub use shared::SharedGraph;
+pub use sparse::CsrMatrix;
+pub use store::{
+    Compaction, Document, GraphConfig, GraphStats, KeywordNode, NumpyGraphStore, SourceInfo, TextNode,
+};
+pub use vectors::EmbeddingMatrix;
+
+// Compile the README example as a doctest
+#[doc = include_str!("../README.md")]
+#[cfg(doctest)]
+pub struct ReadmeDoctests;
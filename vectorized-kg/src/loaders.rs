@@ -0,0 +1,574 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::chunker::CharCursor;
+use crate::{Document, KgError, Metadata, MetadataValue, SourceInfo};
+
+/// Kinds of file the loaders understand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// Plain text, one document per form-feed separated page
+    Text,
+    /// Markdown, one document per heading section
+    Markdown,
+    /// HTML with tags, scripts and styles stripped, split into blocks
+    Html,
+    /// CSV with a header row, one document per record
+    Csv,
+    /// One JSON object per line, one document per object
+    Jsonl,
+}
+
+impl FileType {
+    /// Pick a loader from the file extension, `None` for unsupported files
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "txt" | "text" => Some(FileType::Text),
+            "md" | "markdown" => Some(FileType::Markdown),
+            "html" | "htm" => Some(FileType::Html),
+            "csv" => Some(FileType::Csv),
+            "jsonl" | "ndjson" => Some(FileType::Jsonl),
+            _ => None,
+        }
+    }
+
+    /// Name recorded as `SourceInfo::file_type`: the usual file extension
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileType::Text => "txt",
+            FileType::Markdown => "md",
+            FileType::Html => "html",
+            FileType::Csv => "csv",
+            FileType::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Options for turning files into documents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoaderConfig {
+    /// Descend into subdirectories
+    pub recursive: bool,
+    /// Field of each JSONL record that holds the text
+    pub jsonl_text_field: String,
+    /// CSV columns joined into the text; every column as `header: value` if empty
+    pub csv_text_columns: Vec<String>,
+    /// Tags added to every loaded document's `SourceInfo`
+    pub tags: Vec<String>,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            recursive: true,
+            jsonl_text_field: "text".to_string(),
+            csv_text_columns: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Load every supported file under `dir`, in path order
+///
+/// `SourceInfo::filename` is the path relative to `dir` with `/` separators.
+/// Files with unknown extensions are skipped, as are files that are not UTF-8.
+pub fn load_directory(dir: &Path, config: &LoaderConfig) -> Result<Vec<Document>, KgError> {
+    let max_depth = if config.recursive { usize::MAX } else { 1 };
+    let mut documents = Vec::new();
+    for entry in walkdir::WalkDir::new(dir).max_depth(max_depth).sort_by_file_name() {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let path = entry.path();
+        let file_type = match FileType::from_path(path) {
+            Some(file_type) if entry.file_type().is_file() => file_type,
+            _ => continue,
+        };
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                tracing::warn!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+        let filename = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        documents.extend(load_str(&contents, &filename, file_type, config)?);
+    }
+    tracing::info!("Loaded {} documents from {}", documents.len(), dir.display());
+    Ok(documents)
+}
+
+/// Load a single file, choosing the loader from its extension
+pub fn load_file(path: &Path, config: &LoaderConfig) -> Result<Vec<Document>, KgError> {
+    let file_type =
+        FileType::from_path(path).ok_or_else(|| KgError::InvalidInput(format!("unsupported file type: {}", path.display())))?;
+    let contents = std::fs::read_to_string(path)?;
+    let filename = path.file_name().unwrap_or_default().to_string_lossy();
+    load_str(&contents, &filename, file_type, config)
+}
+
+/// Split `contents` into documents attributed to `filename`
+///
+/// `chunk_idx` counts documents within the file from 0. Only plain text has
+/// pages (numbered from 1); other types leave `page_num` empty. Plain text
+/// pages and Markdown sections are verbatim slices of `contents` and carry
+/// their `char_range`, so `chunk_documents` can map chunks back to the file.
+/// Markdown sections record their `heading` in metadata, and JSONL records
+/// keep their other fields as metadata.
+pub fn load_str(
+    contents: &str,
+    filename: &str,
+    file_type: FileType,
+    config: &LoaderConfig,
+) -> Result<Vec<Document>, KgError> {
+    let derived = |texts: Vec<String>| {
+        texts
+            .into_iter()
+            .map(|text| Part {
+                page_num: None,
+                text,
+                range: None,
+                metadata: Metadata::new(),
+            })
+            .collect()
+    };
+    let verbatim = |page_num: Option<u32>, (from, to): (usize, usize)| Part {
+        page_num,
+        text: contents[from..to].to_string(),
+        range: Some((from, to)),
+        metadata: Metadata::new(),
+    };
+    let parts: Vec<Part> = match file_type {
+        FileType::Text => text_pages(contents)
+            .into_iter()
+            .map(|(page, range)| verbatim(Some(page), range))
+            .collect(),
+        FileType::Markdown => markdown_sections(contents)
+            .into_iter()
+            .map(|range| {
+                let mut part = verbatim(None, range);
+                part.metadata = section_metadata(&part.text);
+                part
+            })
+            .collect(),
+        FileType::Html => derived(paragraphs(&strip_html(contents)).collect()),
+        FileType::Csv => derived(csv_records(contents, &config.csv_text_columns)?),
+        FileType::Jsonl => jsonl_records(contents, filename, &config.jsonl_text_field),
+    };
+
+    // Byte ranges come in increasing order
+    let mut starts = CharCursor::default();
+    let mut ends = CharCursor::default();
+    Ok(parts
+        .into_iter()
+        .enumerate()
+        .map(|(chunk_idx, part)| Document {
+            text: part.text,
+            source: SourceInfo {
+                filename: filename.to_string(),
+                page_num: part.page_num,
+                file_type: file_type.as_str().to_string(),
+                chunk_idx: Some(chunk_idx),
+                char_range: part
+                    .range
+                    .map(|(from, to)| (starts.advance(contents, from), ends.advance(contents, to))),
+                tags: config.tags.clone(),
+            },
+            metadata: part.metadata,
+        })
+        .collect())
+}
+
+/// `heading` metadata of a Markdown section that starts with a heading line
+fn section_metadata(section: &str) -> Metadata {
+    let mut metadata = Metadata::new();
+    let first_line = section.lines().next().unwrap_or_default();
+    if first_line.starts_with('#') {
+        let heading = first_line.trim_start_matches('#').trim();
+        metadata.insert("heading".to_string(), heading.into());
+    }
+    metadata
+}
+
+/// One document's worth of a file, with its byte range when the text is verbatim
+struct Part {
+    page_num: Option<u32>,
+    text: String,
+    range: Option<(usize, usize)>,
+    metadata: Metadata,
+}
+
+/// Non-empty blank-line separated paragraphs, with inner whitespace collapsed
+fn paragraphs(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+}
+
+/// Byte range of every non-blank form-feed separated page, trimmed, with its page number
+fn text_pages(contents: &str) -> Vec<(u32, (usize, usize))> {
+    let mut pages = Vec::new();
+    let mut offset = 0;
+    for (page, text) in contents.split('\x0c').enumerate() {
+        if let Some(range) = trimmed_range(text) {
+            pages.push((page as u32 + 1, (offset + range.0, offset + range.1)));
+        }
+        offset += text.len() + 1;
+    }
+    pages
+}
+
+/// Byte range of `text` without leading and trailing whitespace, `None` if blank
+fn trimmed_range(text: &str) -> Option<(usize, usize)> {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let start = text.len() - text.trim_start().len();
+    Some((start, start + trimmed.len()))
+}
+
+/// Byte range of every ATX heading section, starting at its heading line
+///
+/// Text before the first heading forms its own section; `#` lines inside
+/// fenced code blocks are not headings. Sections with no body are dropped.
+fn markdown_sections(contents: &str) -> Vec<(usize, usize)> {
+    let mut sections = Vec::new();
+    // Start of the current section and of its body
+    let mut section = (0, 0);
+    let mut in_fence = false;
+    let mut flush = |(start, body): (usize, usize), end: usize| {
+        if !contents[body..end].trim().is_empty() {
+            let (from, to) = trimmed_range(&contents[start..end]).unwrap();
+            sections.push((start + from, start + to));
+        }
+    };
+
+    let mut offset = 0;
+    for line in contents.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        let is_heading = !in_fence
+            && (1..=6).contains(&level)
+            && trimmed[level..].chars().next().is_none_or(char::is_whitespace);
+        if is_heading {
+            flush(section, offset);
+            section = (offset, offset + line.len());
+        }
+        offset += line.len();
+    }
+    flush(section, contents.len());
+    sections
+}
+
+/// Elements whose start or end separates paragraphs
+const BLOCK_TAGS: &[&str] = &[
+    "p", "div", "br", "li", "ul", "ol", "tr", "table", "h1", "h2", "h3", "h4", "h5", "h6", "section",
+    "article", "header", "footer", "blockquote", "pre", "title", "hr",
+];
+
+/// Visible text of an HTML document, with block elements separated by blank lines
+fn strip_html(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..start]));
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if (name == "script" || name == "style") && !tag.starts_with('/') {
+            // Search the lowercased copy at the same offset: ASCII lowercasing
+            // keeps byte positions, and the document is lowercased only once
+            let offset = html.len() - rest.len();
+            let close = format!("</{}", name);
+            rest = lower[offset..].find(&close).map_or("", |at| &rest[at..]);
+            continue;
+        }
+        if BLOCK_TAGS.contains(&name.as_str()) {
+            text.push_str("\n\n");
+        } else {
+            text.push(' ');
+        }
+    }
+    text.push_str(&decode_entities(rest));
+    text
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').filter(|&end| end <= 10).map(|end| &rest[1..end + 1]);
+        let replacement = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+            }
+        });
+        match (entity, replacement) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Text of every CSV record, from `columns` or from all columns as `header: value`
+fn csv_records(contents: &str, columns: &[String]) -> Result<Vec<String>, KgError> {
+    let invalid = |e: csv::Error| KgError::InvalidInput(format!("invalid CSV: {}", e));
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(contents.as_bytes());
+    let headers = reader.headers().map_err(invalid)?.clone();
+    let selected: Vec<usize> = if columns.is_empty() {
+        (0..headers.len()).collect()
+    } else {
+        columns
+            .iter()
+            .map(|column| {
+                headers
+                    .iter()
+                    .position(|h| h == column)
+                    .ok_or_else(|| KgError::InvalidInput(format!("CSV has no column {:?}", column)))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(invalid)?;
+        let fields: Vec<String> = selected
+            .iter()
+            .filter_map(|&i| record.get(i).map(|value| (i, value.trim())))
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(i, value)| {
+                if columns.is_empty() {
+                    format!("{}: {}", &headers[i], value)
+                } else {
+                    value.to_string()
+                }
+            })
+            .collect();
+        if !fields.is_empty() {
+            records.push(fields.join("\n"));
+        }
+    }
+    Ok(records)
+}
+
+/// The `field` string of every JSONL record, with the other fields as
+/// metadata; records without it are skipped
+fn jsonl_records(contents: &str, filename: &str, field: &str) -> Vec<Part> {
+    let mut records = Vec::new();
+    for (line_no, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: serde_json::Value = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(e) => {
+                tracing::warn!("Skipping line {} of {}: {}", line_no + 1, filename, e);
+                continue;
+            }
+        };
+        match record.get(field).and_then(|text| text.as_str()) {
+            Some(text) if !text.trim().is_empty() => records.push(Part {
+                page_num: None,
+                text: text.trim().to_string(),
+                range: None,
+                metadata: record_metadata(&record, field),
+            }),
+            _ => tracing::warn!("Skipping line {} of {}: no {:?} string", line_no + 1, filename, field),
+        }
+    }
+    records
+}
+
+/// Metadata from the other top-level fields of a JSONL record
+///
+/// Strings, numbers, booleans and arrays of strings are kept; nested objects,
+/// nulls and mixed arrays are dropped.
+fn record_metadata(record: &serde_json::Value, text_field: &str) -> Metadata {
+    let Some(fields) = record.as_object() else {
+        return Metadata::new();
+    };
+    fields
+        .iter()
+        .filter(|(key, _)| key.as_str() != text_field)
+        .filter_map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::String(text) => MetadataValue::Text(text.clone()),
+                serde_json::Value::Bool(flag) => MetadataValue::Bool(*flag),
+                serde_json::Value::Number(number) => match number.as_i64() {
+                    Some(int) => MetadataValue::Int(int),
+                    None => MetadataValue::Float(number.as_f64()?),
+                },
+                serde_json::Value::Array(items) => MetadataValue::List(
+                    items
+                        .iter()
+                        .map(|item| item.as_str().map(str::to_string))
+                        .collect::<Option<_>>()?,
+                ),
+                _ => return None,
+            };
+            Some((key.clone(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(documents: &[Document]) -> Vec<&str> {
+        documents.iter().map(|d| d.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_text_pages() {
+        let text = " first  para\n\nsecond\x0c\n\x0cthïrd\n";
+        let docs = load_str(text, "a.txt", FileType::Text, &LoaderConfig::default()).unwrap();
+        assert_eq!(texts(&docs), vec!["first  para\n\nsecond", "thïrd"]);
+        let pages: Vec<_> = docs.iter().map(|d| (d.source.page_num, d.source.chunk_idx)).collect();
+        assert_eq!(pages, vec![(Some(1), Some(0)), (Some(3), Some(1))]);
+        assert_eq!(docs[1].source.char_range, Some((23, 28)));
+        assert_eq!(docs[0].source.file_type, "txt");
+    }
+
+    #[test]
+    fn test_markdown_sections() {
+        let md = "intro line\n\n# Solar\nPanels make power.\n\n## Empty\n\n## Code\n```\n# not a heading\n```\n";
+        let docs = load_str(md, "notes.md", FileType::Markdown, &LoaderConfig::default()).unwrap();
+        assert_eq!(
+            texts(&docs),
+            vec!["intro line", "# Solar\nPanels make power.", "## Code\n```\n# not a heading\n```"]
+        );
+        assert_eq!(docs[2].source.chunk_idx, Some(2));
+        assert_eq!(docs[2].source.page_num, None);
+        let (start, end) = docs[1].source.char_range.unwrap();
+        assert_eq!(&md[start..end], docs[1].text);
+        assert_eq!(docs[1].metadata.get("heading"), Some(&MetadataValue::from("Solar")));
+        assert!(docs[0].metadata.is_empty());
+        assert_eq!(docs[0].source.file_type, "md");
+    }
+
+    #[test]
+    fn test_html_strips_tags() {
+        let html = "<html><head><title>Trains</title><style>p { color: red }</style></head>\
+                    <body><!-- hidden --><p>Electric <b>trains</b> &amp; trams</p>\
+                    <script>var x = '<p>';</script><div>caf&#233; &lt;3</div></body></html>";
+        let docs = load_str(html, "page.html", FileType::Html, &LoaderConfig::default()).unwrap();
+        assert_eq!(texts(&docs), vec!["Trains", "Electric trains & trams", "café <3"]);
+
+        let html = "<p>crème</p><SCRIPT>if (a < b) {}</Script><p>brûlée</p><style>x</STYLE>";
+        let docs = load_str(html, "page.html", FileType::Html, &LoaderConfig::default()).unwrap();
+        assert_eq!(texts(&docs), vec!["crème", "brûlée"]);
+    }
+
+    #[test]
+    fn test_csv_rows() {
+        let csv = "title,body,year\nSolar,\"panels, cells\",2020\nPasta,recipes,\n";
+        let all = load_str(csv, "t.csv", FileType::Csv, &LoaderConfig::default()).unwrap();
+        assert_eq!(texts(&all), vec!["title: Solar\nbody: panels, cells\nyear: 2020", "title: Pasta\nbody: recipes"]);
+        assert_eq!(all[1].source.chunk_idx, Some(1));
+
+        let config = LoaderConfig {
+            csv_text_columns: vec!["body".to_string()],
+            ..LoaderConfig::default()
+        };
+        let bodies = load_str(csv, "t.csv", FileType::Csv, &config).unwrap();
+        assert_eq!(texts(&bodies), vec!["panels, cells", "recipes"]);
+
+        let config = LoaderConfig {
+            csv_text_columns: vec!["missing".to_string()],
+            ..LoaderConfig::default()
+        };
+        assert!(load_str(csv, "t.csv", FileType::Csv, &config).is_err());
+    }
+
+    #[test]
+    fn test_jsonl_field() {
+        let jsonl = "{\"content\": \"first\"}\nnot json\n{\"other\": 1}\n\n{\"content\": \"second\"}\n";
+        let config = LoaderConfig {
+            jsonl_text_field: "content".to_string(),
+            ..LoaderConfig::default()
+        };
+        let docs = load_str(jsonl, "r.jsonl", FileType::Jsonl, &config).unwrap();
+        assert_eq!(texts(&docs), vec!["first", "second"]);
+        assert_eq!(docs[1].source.chunk_idx, Some(1));
+
+        let jsonl = r#"{"text": "report", "author": "Ada", "year": 2021, "score": 0.5, "draft": false, "access": ["staff"], "extra": {"a": 1}}"#;
+        let docs = load_str(jsonl, "r.jsonl", FileType::Jsonl, &LoaderConfig::default()).unwrap();
+        let metadata = &docs[0].metadata;
+        assert_eq!(metadata.len(), 5);
+        assert_eq!(metadata["author"], MetadataValue::from("Ada"));
+        assert_eq!(metadata["year"], MetadataValue::Int(2021));
+        assert_eq!(metadata["score"], MetadataValue::Float(0.5));
+        assert_eq!(metadata["draft"], MetadataValue::Bool(false));
+        assert_eq!(metadata["access"], MetadataValue::from(vec!["staff".to_string()]));
+    }
+
+    #[test]
+    fn test_load_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::write(root.join("b.txt"), "bravo").unwrap();
+        std::fs::write(root.join("a.md"), "# Alpha\nalpha body").unwrap();
+        std::fs::write(root.join("sub/c.jsonl"), "{\"text\": \"charlie\"}").unwrap();
+        std::fs::write(root.join("image.png"), [0u8, 1, 2]).unwrap();
+
+        let docs = load_directory(root, &LoaderConfig::default()).unwrap();
+        let names: Vec<_> = docs.iter().map(|d| d.source.filename.as_str()).collect();
+        assert_eq!(names, vec!["a.md", "b.txt", "sub/c.jsonl"]);
+
+        let config = LoaderConfig {
+            recursive: false,
+            ..LoaderConfig::default()
+        };
+        assert_eq!(load_directory(root, &config).unwrap().len(), 2);
+        assert_eq!(load_file(&root.join("b.txt"), &config).unwrap()[0].text, "bravo");
+        assert!(load_file(&root.join("image.png"), &config).is_err());
+    }
+}
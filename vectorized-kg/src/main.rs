@@ -1,697 +1,709 @@
-use ndarray::Array2;
-use rand_chacha::rand_core::{RngCore, SeedableRng};
-use rand_chacha::ChaCha8Rng;
-use std::hash::{Hash, Hasher};
-use serde::{Deserialize, Serialize};
-
-/// Represents a text node in the knowledge graph
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TextNode {
-    pub id: usize,
-    pub text: String,
-    pub source: SourceInfo,
-    pub embedding: Vec<f32>,
-    pub token_count: usize,
+use clap::{Args, Parser, Subcommand};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use vectorized_kg::{
+    chunk_documents, load_directory, ChunkBoundary, ChunkerConfig, CommandEmbedder, Embedder, Filter, Fusion,
+    GraphConfig, HashEmbedder, HybridConfig, KeyphraseMethod, Language, LoaderConfig, Metadata, MetadataValue,
+    NearDuplicateMode, NumpyGraphStore, RetrievalConfig, RetrievalPath, SnapshotFormat, SourceInfo,
+};
+
+/// Build and query vectorized knowledge graphs
+#[derive(Debug, Parser)]
+#[command(name = "vectorized-kg", version)]
+struct Cli {
+    /// Snapshot to read and write; the extension picks the format (.vkg, .vkgz or .json)
+    #[arg(long, short, global = true, default_value = "graph.vkg")]
+    graph: PathBuf,
+
+    #[command(flatten)]
+    embedder: EmbedderArgs,
+
+    #[command(subcommand)]
+    command: Command,
 }
 
-/// Source information with provenance
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
-pub struct SourceInfo {
-    pub filename: String,
-    pub page_num: Option<u32>,
-    pub file_type: String,
-    pub chunk_idx: Option<usize>,
+// Parsed once per run, so the size of `Ingest` does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Add the supported files of a directory to the graph, creating it if needed
+    ///
+    /// Reads .txt, .md, .html, .csv and .jsonl files, descending into subdirectories.
+    Ingest {
+        dir: PathBuf,
+        #[command(flatten)]
+        loader: LoaderArgs,
+        #[command(flatten)]
+        chunker: ChunkerArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Print the texts closest to a query, with their sources
+    Query {
+        text: String,
+        /// Number of texts to print
+        #[arg(long, short, default_value_t = 5)]
+        k: usize,
+        /// Fuse BM25 matches on the query words with the embedding ranking
+        #[arg(long)]
+        hybrid: bool,
+        /// Combine the rankings by weighted scores instead of reciprocal ranks
+        #[arg(long, requires = "hybrid")]
+        linear: bool,
+        /// Weight of the BM25 ranking in hybrid search
+        #[arg(long, requires = "hybrid", default_value_t = HybridConfig::default().lexical_weight)]
+        lexical_weight: f32,
+        /// Weight of the embedding ranking in hybrid search
+        #[arg(long, requires = "hybrid", default_value_t = HybridConfig::default().vector_weight)]
+        vector_weight: f32,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Retrieve texts by vector similarity and keyword-graph expansion, with the paths that found them
+    Retrieve {
+        text: String,
+        /// Number of texts to print
+        #[arg(long, short, default_value_t = 5)]
+        k: usize,
+        /// Keyword-graph hops from the keywords closest to the query
+        #[arg(long, default_value_t = RetrievalConfig::default().hops)]
+        hops: usize,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Show the texts related to a keyword and its adjacent keywords
+    Keywords {
+        term: String,
+        /// Number of texts and keywords to print
+        #[arg(long, short, default_value_t = 10)]
+        k: usize,
+    },
+    /// List pairs of live texts that are near-duplicates of each other
+    Duplicates,
+    /// Report node and edge counts and snapshot metadata
+    Stats,
+    /// Write the live nodes and edges as JSON
+    Export {
+        /// Output file; standard output if omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
 }
 
-/// Represents a keyword node in the knowledge graph
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct KeywordNode {
-    pub id: usize,
-    pub text: String,
-    pub embedding: Vec<f32>,
+/// Which embedder turns texts and queries into vectors
+///
+/// A graph must be queried with the embedder it was built with.
+#[derive(Debug, Args)]
+struct EmbedderArgs {
+    /// Embedder for texts and queries
+    ///
+    /// `hash` derives vectors from a hash of the text: they carry no meaning,
+    /// so vector search only finds texts identical to the query. Use
+    /// `command` with `--embed-command` to embed with a real model.
+    #[arg(long, global = true, value_enum, default_value = "hash")]
+    embedder: EmbedderArg,
+    /// Program run by `--embedder command`: reads a JSON array of texts on
+    /// standard input and writes a JSON array of vectors to standard output
+    #[arg(long, global = true, value_name = "PROGRAM")]
+    embed_command: Option<String>,
+    /// Argument for the `--embed-command` program (repeatable)
+    #[arg(long = "embed-arg", global = true, value_name = "ARG", allow_hyphen_values = true)]
+    embed_args: Vec<String>,
 }
 
-/// In-memory graph store using ndarray for vector operations
-#[derive(Debug, Clone)]
-pub struct NumpyGraphStore {
-    texts: Vec<TextNode>,
-    keywords: Vec<KeywordNode>,
-    u_mat: Option<Array2<f32>>,
-    pred_mat: Option<Array2<u8>>,
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum EmbedderArg {
+    Hash,
+    Command,
 }
 
-impl NumpyGraphStore {
-    pub fn new() -> Self {
-        Self {
-            texts: Vec::new(),
-            keywords: Vec::new(),
-            u_mat: None,
-            pred_mat: None,
-        }
-    }
-
-    /// Build knowledge graph from documents
-    pub fn build_kg(&mut self, documents: &[Document], config: &GraphConfig) {
-        tracing::info!("Building knowledge graph from {} documents...", documents.len());
-
-        let texts: Vec<String> = documents.iter().map(|d| d.text.clone()).collect();
-        let sources: Vec<SourceInfo> = documents.iter().map(|d| d.source.clone()).collect();
-
-        tracing::info!("Generating embeddings...");
-        let vectors: Vec<Vec<f32>> = self.mock_embeddings(&texts, config.embedding_dim);
-        let token_counts: Vec<usize> = texts.iter().map(|t| t.split_whitespace().count()).collect();
-
-        tracing::info!("Removing duplicate texts...");
-        let (texts, sources, vectors, token_counts) =
-            self.remove_duplicates(texts, sources, vectors, token_counts);
-
-        tracing::info!("After deduplication: {} texts", texts.len());
-
-        self.texts = texts
-            .into_iter()
-            .enumerate()
-            .map(|(id, text)| TextNode {
-                id,
-                text,
-                source: sources[id].clone(),
-                embedding: vectors[id].clone(),
-                token_count: token_counts[id],
-            })
-            .collect();
-
-        tracing::info!("Extracting keywords...");
-        let keywords = self.extract_keywords(&self.texts);
-        tracing::info!("Extracted {} unique keywords", keywords.len());
-
-        let keyvectors: Vec<Vec<f32>> = self.mock_embeddings(&keywords, config.embedding_dim);
-
-        self.keywords = keywords
-            .into_iter()
-            .enumerate()
-            .map(|(id, text)| KeywordNode {
-                id,
-                text,
-                embedding: keyvectors[id].clone(),
-            })
-            .collect();
-
-        tracing::info!("Building keyword relationships...");
-        self.build_keyword_relationships();
-    }
-
-    fn mock_embeddings(&self, texts: &[String], dim: usize) -> Vec<Vec<f32>> {
-        // Deterministic embeddings based on text hash
-        texts
-            .iter()
-            .map(|text| {
-                let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                text.hash(&mut hasher);
-                let seed = hasher.finish() as u64;
-                let mut local_rng = ChaCha8Rng::seed_from_u64(seed);
-                let mut vec = vec![0.0f32; dim];
-                for v in vec.iter_mut() {
-                    *v = (local_rng.next_u32() as f32) / (u32::MAX as f32) * 2.0 - 1.0;
-                }
-                // Normalize
-                let norm: f32 = vec.iter().map(|x| x * x).sum::<f32>().sqrt();
-                if norm > 0.0 {
-                    vec.iter_mut().for_each(|x| *x /= norm);
-                }
-                vec
-            })
-            .collect()
-    }
-
-    fn remove_duplicates(
-        &self,
-        texts: Vec<String>,
-        sources: Vec<SourceInfo>,
-        vectors: Vec<Vec<f32>>,
-        token_counts: Vec<usize>,
-    ) -> (Vec<String>, Vec<SourceInfo>, Vec<Vec<f32>>, Vec<usize>) {
-        let mut seen = std::collections::HashSet::new();
-        let mut result = Vec::new();
-
-        for (i, text) in texts.into_iter().enumerate() {
-            if !seen.contains(&text) {
-                seen.insert(text.clone());
-                result.push((text, sources[i].clone(), vectors[i].clone(), token_counts[i]));
+impl EmbedderArgs {
+    fn embedder(&self, dim: usize) -> Result<Arc<dyn Embedder>, Box<dyn std::error::Error>> {
+        Ok(match self.embedder {
+            EmbedderArg::Hash => Arc::new(HashEmbedder::new(dim)),
+            EmbedderArg::Command => {
+                let program = self
+                    .embed_command
+                    .clone()
+                    .ok_or("--embedder command needs --embed-command")?;
+                Arc::new(CommandEmbedder::new(program, self.embed_args.clone(), dim))
             }
-        }
-
-        let texts: Vec<String> = result.iter().map(|(t, _, _, _)| t.clone()).collect();
-        let sources: Vec<SourceInfo> = result.iter().map(|(_, s, _, _)| s.clone()).collect();
-        let vectors: Vec<Vec<f32>> = result.iter().map(|(_, _, v, _)| v.clone()).collect();
-        let token_counts: Vec<usize> = result.iter().map(|(_, _, _, c)| *c).collect();
-
-        (texts, sources, vectors, token_counts)
+        })
     }
+}
 
-    fn extract_keywords(&self, texts: &[TextNode]) -> Vec<String> {
-        // Simple placeholder - extract common words
-        let mut keywords = std::collections::HashSet::new();
+/// How `ingest` reads files
+#[derive(Debug, Default, Args)]
+struct LoaderArgs {
+    /// Only read files directly inside the directory
+    #[arg(long)]
+    no_recursive: bool,
+    /// Field of each JSONL record that holds the text
+    #[arg(long, default_value = "text")]
+    text_field: String,
+    /// CSV columns to index (repeatable); all columns if omitted
+    #[arg(long = "csv-column")]
+    csv_columns: Vec<String>,
+    /// Tag every ingested text (repeatable), for filtering searches with `--tag`
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+}
 
-        for text_node in texts {
-            for word in text_node.text.split_whitespace() {
-                if word.len() > 3 {
-                    keywords.insert(word.to_lowercase());
-                }
-            }
+impl LoaderArgs {
+    fn config(&self) -> LoaderConfig {
+        LoaderConfig {
+            recursive: !self.no_recursive,
+            jsonl_text_field: self.text_field.clone(),
+            csv_text_columns: self.csv_columns.clone(),
+            tags: self.tags.clone(),
         }
-
-        keywords.into_iter().collect()
     }
+}
 
-    fn build_keyword_relationships(&mut self) {
-        let n_texts = self.texts.len();
-        let n_keywords = self.keywords.len();
+/// Restricts `query` and `retrieve` to matching sources; all given conditions must hold
+#[derive(Debug, Default, Args)]
+struct FilterArgs {
+    /// Only texts from this file (repeatable: any of them)
+    #[arg(long = "file")]
+    files: Vec<String>,
+    /// Only texts of this file type: txt, md, html, csv or jsonl
+    #[arg(long)]
+    file_type: Option<String>,
+    /// Only texts on this page or later
+    #[arg(long)]
+    min_page: Option<u32>,
+    /// Only texts on this page or earlier
+    #[arg(long)]
+    max_page: Option<u32>,
+    /// Only texts carrying this tag (repeatable: all of them)
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    /// Only texts whose metadata has KEY=VALUE, or a KEY list containing VALUE (repeatable: all of them)
+    #[arg(long = "meta", value_name = "KEY=VALUE", value_parser = parse_meta)]
+    meta: Vec<(String, String)>,
+}
 
-        if n_texts == 0 || n_keywords == 0 {
-            return;
+impl FilterArgs {
+    fn filter(&self) -> Option<Filter> {
+        let mut filters = Vec::new();
+        if !self.files.is_empty() {
+            filters.push(Filter::filename_in(self.files.iter().cloned()));
         }
-
-        // Create U matrix for demonstration
-        let mut u_mat = Array2::zeros((n_texts, n_keywords));
-        for i in 0..n_texts {
-            for j in 0..n_keywords {
-                u_mat[[i, j]] = (i + j) as f32 / (n_texts + n_keywords) as f32;
-            }
+        if let Some(file_type) = &self.file_type {
+            filters.push(Filter::file_type(file_type.clone()));
         }
-
-        self.u_mat = Some(u_mat);
-    }
-
-    pub fn search_similar_texts(&self, query_vec: &[f32], k: usize) -> Vec<(usize, f32)> {
-        if self.texts.is_empty() {
-            return Vec::new();
+        if self.min_page.is_some() || self.max_page.is_some() {
+            filters.push(Filter::Pages {
+                min: self.min_page,
+                max: self.max_page,
+            });
         }
-
-        let mut distances: Vec<(usize, f32)> = self
-            .texts
-            .iter()
-            .enumerate()
-            .map(|(i, text)| (i, cosine_distance(query_vec, &text.embedding)))
-            .collect();
-
-        distances.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        let k = std::cmp::min(k, self.texts.len());
-        distances.truncate(k);
-        distances
+        filters.extend(self.tags.iter().cloned().map(Filter::Tag));
+        filters.extend(self.meta.iter().map(|(key, value)| Filter::metadata(key.clone(), metadata_value(value))));
+        (!filters.is_empty()).then_some(Filter::All(filters))
     }
+}
 
-    pub fn search_similar_keywords(&self, query_vec: &[f32], k: usize) -> Vec<(usize, f32)> {
-        if self.keywords.is_empty() {
-            return Vec::new();
-        }
+fn parse_meta(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got {:?}", arg))?;
+    Ok((key.to_string(), value.to_string()))
+}
 
-        let mut distances: Vec<(usize, f32)> = self
-            .keywords
-            .iter()
-            .enumerate()
-            .map(|(i, kw)| (i, cosine_distance(query_vec, &kw.embedding)))
-            .collect();
-
-        distances.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        let k = std::cmp::min(k, self.keywords.len());
-        distances.truncate(k);
-        distances
+/// Integers, floats and booleans compare as such; anything else as text
+fn metadata_value(value: &str) -> MetadataValue {
+    if let Ok(int) = value.parse() {
+        MetadataValue::Int(int)
+    } else if let Ok(float) = value.parse() {
+        MetadataValue::Float(float)
+    } else if let Ok(flag) = value.parse() {
+        MetadataValue::Bool(flag)
+    } else {
+        MetadataValue::Text(value.to_string())
     }
+}
 
-    pub fn get_keyword_related_texts(&self, keyword_idx: usize, k: usize) -> Vec<usize> {
-        if let Some(u_mat) = &self.u_mat {
-            if keyword_idx >= u_mat.ncols() {
-                return Vec::new();
-            }
+/// How `ingest` splits documents into chunks
+#[derive(Debug, Args)]
+struct ChunkerArgs {
+    /// Largest chunk, in tokens
+    #[arg(long, default_value_t = ChunkerConfig::default().max_tokens)]
+    max_tokens: usize,
+    /// Tokens shared by consecutive chunks
+    #[arg(long, default_value_t = ChunkerConfig::default().overlap_tokens)]
+    overlap: usize,
+    /// Preferred place to end a chunk
+    #[arg(long, value_enum, default_value = "paragraph")]
+    boundary: BoundaryArg,
+}
 
-            let mut scores: Vec<(usize, f32)> = (0..u_mat.nrows())
-                .map(|i| (i, u_mat[[i, keyword_idx]]))
-                .collect();
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum BoundaryArg {
+    Paragraph,
+    Sentence,
+    Token,
+}
 
-            scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-            let k = std::cmp::min(k, u_mat.nrows() as usize);
-            scores.truncate(k);
-            scores.into_iter().map(|(i, _)| i).collect()
-        } else {
-            Vec::new()
+impl ChunkerArgs {
+    fn config(&self) -> ChunkerConfig {
+        ChunkerConfig {
+            max_tokens: self.max_tokens,
+            overlap_tokens: self.overlap,
+            boundary: match self.boundary {
+                BoundaryArg::Paragraph => ChunkBoundary::Paragraph,
+                BoundaryArg::Sentence => ChunkBoundary::Sentence,
+                BoundaryArg::Token => ChunkBoundary::Token,
+            },
         }
     }
-
-    pub fn get_adjacent_keywords(&self, keyword_idx: usize, k: usize) -> Vec<usize> {
-        if self.keywords.is_empty() {
-            return Vec::new();
-        }
-
-        let mut adjacent: Vec<usize> = (0..self.keywords.len())
-            .filter(|&i| i != keyword_idx)
-            .collect();
-
-        let k = std::cmp::min(k, adjacent.len());
-        adjacent.truncate(k);
-        adjacent
-    }
-
-    pub fn get_texts(&self) -> &[TextNode] {
-        &self.texts
-    }
-
-    pub fn get_keywords(&self) -> &[KeywordNode] {
-        &self.keywords
-    }
-
-    pub fn get_sources(&self) -> Vec<&SourceInfo> {
-        self.texts.iter().map(|t| &t.source).collect::<Vec<_>>()
-    }
-
-    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let serializable = GraphStoreSnapshot {
-            texts: self.texts.clone(),
-            keywords: self.keywords.clone(),
-        };
-        let data = serde_json::to_string_pretty(&serializable)?;
-        std::fs::write(path, data)?;
-        Ok(())
-    }
-
-    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let data = std::fs::read_to_string(path)?;
-        let snapshot: GraphStoreSnapshot = serde_json::from_str(&data)?;
-        Ok(Self {
-            texts: snapshot.texts,
-            keywords: snapshot.keywords,
-            u_mat: None,
-            pred_mat: None,
-        })
-    }
 }
 
-/// Compute cosine distance between two vectors
-fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
-    if a.len() != b.len() {
-        return 1.0;
-    }
-
-    let mut dot = 0.0;
-    let mut norm_a = 0.0;
-    let mut norm_b = 0.0;
-
-    for i in 0..a.len() {
-        dot += a[i] * b[i];
-        norm_a += a[i] * a[i];
-        norm_b += b[i] * b[i];
-    }
-
-    let norm_a = norm_a.sqrt();
-    let norm_b = norm_b.sqrt();
-
-    if norm_a == 0.0 || norm_b == 0.0 {
-        return 1.0;
-    }
+/// `GraphConfig` overrides; a config file is read first, flags win over it
+///
+/// Only used when a new graph is created: an existing snapshot keeps the
+/// configuration it was built with.
+#[derive(Debug, Default, Args)]
+struct ConfigArgs {
+    /// TOML or JSON file with `GraphConfig` fields (chosen by extension)
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[arg(long)]
+    embedding_dim: Option<usize>,
+    #[arg(long)]
+    k_neighbors: Option<usize>,
+    #[arg(long)]
+    trust_num: Option<usize>,
+    #[arg(long)]
+    connect_threshold: Option<f32>,
+    #[arg(long)]
+    bm25_k1: Option<f32>,
+    #[arg(long)]
+    bm25_b: Option<f32>,
+    #[arg(long)]
+    similarity_edges: bool,
+    #[arg(long)]
+    max_path_hops: Option<usize>,
+    #[arg(long)]
+    use_hnsw: bool,
+    #[arg(long)]
+    hnsw_m: Option<usize>,
+    #[arg(long)]
+    hnsw_ef_search: Option<usize>,
+    /// Stopword list and stemmer language
+    #[arg(long, value_enum)]
+    language: Option<LanguageArg>,
+    /// Stem keywords
+    #[arg(long)]
+    stemming: bool,
+    /// Also extract multi-word keyphrases
+    #[arg(long, value_enum)]
+    keyphrases: Option<KeyphraseArg>,
+    /// Keep only the best-ranked keywords of each text
+    #[arg(long)]
+    max_keywords: Option<usize>,
+    /// What to do with texts nearly identical to one already ingested
+    #[arg(long, value_enum)]
+    near_duplicates: Option<NearDuplicateArg>,
+    /// Smallest estimated Jaccard similarity of near-duplicates, in [0, 1]
+    #[arg(long)]
+    near_threshold: Option<f32>,
+}
 
-    1.0 - dot / (norm_a * norm_b)
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum NearDuplicateArg {
+    Off,
+    Merge,
+    Report,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Document {
-    pub text: String,
-    pub source: SourceInfo,
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum LanguageArg {
+    English,
+    French,
+    German,
+    Spanish,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GraphConfig {
-    pub embedding_dim: usize,
-    pub k_neighbors: usize,
-    pub trust_num: usize,
-    pub negative_multiplier: usize,
-    pub connect_threshold: f32,
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum KeyphraseArg {
+    None,
+    Rake,
+    Textrank,
 }
 
-impl Default for GraphConfig {
-    fn default() -> Self {
-        Self {
-            embedding_dim: 768,
-            k_neighbors: 30,
-            trust_num: 5,
-            negative_multiplier: 7,
-            connect_threshold: 0.2,
+impl ConfigArgs {
+    fn resolve(&self) -> Result<GraphConfig, Box<dyn std::error::Error>> {
+        let mut config = match &self.config {
+            Some(path) => read_config(path)?,
+            None => GraphConfig::default(),
+        };
+        macro_rules! set {
+            ($($field:ident),*) => {
+                $(if let Some(value) = self.$field {
+                    config.$field = value;
+                })*
+            };
+        }
+        set!(embedding_dim, k_neighbors, trust_num, connect_threshold, bm25_k1, bm25_b, max_path_hops, hnsw_m, hnsw_ef_search);
+        config.similarity_edges |= self.similarity_edges;
+        config.use_hnsw |= self.use_hnsw;
+        if let Some(language) = self.language {
+            config.keywords.language = match language {
+                LanguageArg::English => Language::English,
+                LanguageArg::French => Language::French,
+                LanguageArg::German => Language::German,
+                LanguageArg::Spanish => Language::Spanish,
+            };
+        }
+        config.keywords.stemming |= self.stemming;
+        if let Some(keyphrases) = self.keyphrases {
+            config.keywords.keyphrases = match keyphrases {
+                KeyphraseArg::None => KeyphraseMethod::None,
+                KeyphraseArg::Rake => KeyphraseMethod::Rake,
+                KeyphraseArg::Textrank => KeyphraseMethod::TextRank,
+            };
         }
+        if self.max_keywords.is_some() {
+            config.keywords.max_keywords_per_text = self.max_keywords;
+        }
+        if let Some(mode) = self.near_duplicates {
+            config.dedup.near_duplicates = match mode {
+                NearDuplicateArg::Off => NearDuplicateMode::Off,
+                NearDuplicateArg::Merge => NearDuplicateMode::Merge,
+                NearDuplicateArg::Report => NearDuplicateMode::Report,
+            };
+        }
+        if let Some(threshold) = self.near_threshold {
+            config.dedup.threshold = threshold;
+        }
+        Ok(config)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct GraphStoreSnapshot {
-    texts: Vec<TextNode>,
-    keywords: Vec<KeywordNode>,
+fn read_config(path: &Path) -> Result<GraphConfig, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if is_json {
+        Ok(serde_json::from_str(&contents)?)
+    } else {
+        Ok(toml::from_str(&contents)?)
+    }
 }
 
-fn main() {
-    println!("vectorized-kg: Knowledge graph implementation for Pingoo");
-    
-    let config = GraphConfig::default();
-    
-    let documents = vec![
-        Document {
-            text: "Hello world from Pingoo".to_string(),
-            source: SourceInfo {
-                filename: "test.txt".to_string(),
-                page_num: Some(1),
-                file_type: "txt".to_string(),
-                chunk_idx: Some(0),
-            },
-        },
-    ];
-
-    let mut store = NumpyGraphStore::new();
-    store.build_kg(&documents, &config);
-    
-    println!("Built graph with {} texts and {} keywords", 
-             store.get_texts().len(), 
-             store.get_keywords().len());
+fn load_graph(path: &Path, embedder: &EmbedderArgs) -> Result<NumpyGraphStore, Box<dyn std::error::Error>> {
+    let mut store = NumpyGraphStore::load(&path.to_string_lossy())
+        .map_err(|e| format!("cannot load {}: {}", path.display(), e))?;
+    let dim = store.config().embedding_dim;
+    store
+        .set_embedder(embedder.embedder(dim)?)
+        .map_err(|e| format!("{}: {} (see --embedder)", path.display(), e))?;
+    Ok(store)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_basic_graph() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Hello world".to_string(),
-                source: SourceInfo {
-                    filename: "test.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-        assert!(store.get_texts().len() > 0);
+fn run(cli: Cli, out: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+    let graph = cli.graph.as_path();
+    match cli.command {
+        Command::Ingest { dir, loader, chunker, config } => {
+            let documents = chunk_documents(&load_directory(&dir, &loader.config())?, &chunker.config());
+            if graph.exists() {
+                let mut store = load_graph(graph, &cli.embedder)?;
+                let added = store.add_documents(&documents)?;
+                writeln!(out, "Added {} of {} texts to {}", added.len(), documents.len(), graph.display())?;
+                store.save(&graph.to_string_lossy())?;
+            } else {
+                let config = config.resolve()?;
+                let embedder = cli.embedder.embedder(config.embedding_dim)?;
+                let mut store = NumpyGraphStore::new();
+                store.build_kg(&documents, &config, embedder)?;
+                writeln!(
+                    out,
+                    "Built {} with {} texts and {} keywords",
+                    graph.display(),
+                    store.get_texts().len(),
+                    store.get_keywords().len()
+                )?;
+                store.save(&graph.to_string_lossy())?;
+            }
+        }
+        Command::Query {
+            text,
+            k,
+            hybrid,
+            linear,
+            lexical_weight,
+            vector_weight,
+            filter,
+        } => {
+            let store = load_graph(graph, &cli.embedder)?;
+            let filter = filter.filter();
+            let hits = if hybrid {
+                let config = HybridConfig {
+                    fusion: if linear { Fusion::Linear } else { Fusion::Rrf },
+                    lexical_weight,
+                    vector_weight,
+                    filter,
+                    ..HybridConfig::default()
+                };
+                store.hybrid_search(&text, k, &config)?
+            } else {
+                match &filter {
+                    Some(filter) => store.search_text_filtered(&text, k, filter)?,
+                    None => store.search_text(&text, k)?,
+                }
+            };
+            for (rank, hit) in hits.into_iter().enumerate() {
+                writeln!(out, "{}. [{:.4}] {}", rank + 1, hit.score, describe_source(&hit.source))?;
+                writeln!(out, "   {}", hit.snippet)?;
+                write_other_sources(out, &hit.other_sources)?;
+                write_metadata(out, &hit.metadata)?;
+            }
+        }
+        Command::Retrieve { text, k, hops, filter } => {
+            let store = load_graph(graph, &cli.embedder)?;
+            let config = RetrievalConfig {
+                top_k: k,
+                hops,
+                filter: filter.filter(),
+                ..RetrievalConfig::default()
+            };
+            for (rank, hit) in store.retrieve(&text, &config)?.into_iter().enumerate() {
+                writeln!(out, "{}. [{:.4}] {}", rank + 1, hit.score, describe_source(&hit.source))?;
+                writeln!(out, "   {}", hit.snippet)?;
+                write_other_sources(out, &hit.other_sources)?;
+                write_metadata(out, &hit.metadata)?;
+                for path in &hit.paths {
+                    writeln!(out, "   via {}", describe_path(&store, path))?;
+                }
+            }
+        }
+        Command::Keywords { term, k } => {
+            let store = load_graph(graph, &cli.embedder)?;
+            let keyword = store
+                .find_keyword(&term)
+                .map(|id| &store.get_keywords()[id])
+                .ok_or_else(|| format!("no keyword {:?} in the graph", term))?;
+
+            writeln!(out, "Texts related to {:?}:", keyword.text)?;
+            for id in store.get_keyword_related_texts(keyword.id, k) {
+                let node = &store.get_texts()[id];
+                let weight = store.get_association(id, keyword.id);
+                writeln!(out, "  [{:.4}] {}: {}", weight, describe_source(&node.source), node.text)?;
+            }
+            writeln!(out, "Adjacent keywords:")?;
+            for (id, weight) in store.get_adjacent_keywords(keyword.id, k) {
+                writeln!(out, "  [{:.4}] {}", weight, store.get_keywords()[id].text)?;
+            }
+        }
+        Command::Duplicates => {
+            let store = NumpyGraphStore::load(&graph.to_string_lossy())?;
+            let texts = store.get_texts();
+            for pair in store.near_duplicates() {
+                writeln!(
+                    out,
+                    "[{:.2}] {} ~ {}",
+                    pair.similarity,
+                    describe_source(&texts[pair.text].source),
+                    describe_source(&texts[pair.duplicate].source)
+                )?;
+            }
+        }
+        Command::Stats => {
+            let store = NumpyGraphStore::load(&graph.to_string_lossy())?;
+            let stats = store.stats();
+            let size = std::fs::metadata(graph)?.len();
+            writeln!(out, "snapshot:      {} ({} bytes, {:?})", graph.display(), size, SnapshotFormat::from_path(graph))?;
+            writeln!(out, "model:         {}", store.model_id().unwrap_or("unknown"))?;
+            writeln!(out, "texts:         {} live of {}", stats.live_texts, stats.texts)?;
+            writeln!(out, "keywords:      {} live of {}", stats.live_keywords, stats.keywords)?;
+            writeln!(out, "associations:  {}", stats.associations)?;
+            writeln!(out, "keyword edges: {}", stats.keyword_edges)?;
+            writeln!(out, "\n[config]\n{}", toml::to_string(store.config())?)?;
+        }
+        Command::Export { output } => {
+            let store = NumpyGraphStore::load(&graph.to_string_lossy())?;
+            let export = export_graph(&store);
+            match output {
+                Some(path) => std::fs::write(path, serde_json::to_vec_pretty(&export)?)?,
+                None => writeln!(out, "{}", serde_json::to_string_pretty(&export)?)?,
+            }
+        }
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_save_load() {
-        let config = GraphConfig::default();
-        let documents = vec![Document {
-            text: "Test document".to_string(),
-            source: SourceInfo {
-                filename: "test.txt".to_string(),
-                page_num: Some(1),
-                file_type: "txt".to_string(),
-                chunk_idx: Some(0),
-            },
-        }];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-        let temp_dir = tempfile::tempdir().unwrap();
-        let path = temp_dir.path().join("graph.json");
-        store.save(path.to_str().unwrap()).unwrap();
-        let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
-        assert_eq!(store.get_texts().len(), loaded.get_texts().len());
+fn describe_source(source: &SourceInfo) -> String {
+    let mut description = source.filename.clone();
+    if let Some(page) = source.page_num {
+        description += &format!(" p.{}", page);
     }
-
-    #[test]
-    fn test_cosine_distance() {
-        let a = vec![1.0, 0.0, 0.0];
-        let b = vec![0.0, 1.0, 0.0];
-        let dist = cosine_distance(&a, &b);
-        assert!((dist - 1.0).abs() < 1e-6);
+    if let Some(chunk) = source.chunk_idx {
+        description += &format!(" #{}", chunk);
     }
-
-    #[test]
-    fn test_search_bounds() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Doc 1".to_string(),
-                source: SourceInfo {
-                    filename: "doc1.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-            Document {
-                text: "Doc 2".to_string(),
-                source: SourceInfo {
-                    filename: "doc2.txt".to_string(),
-                    page_num: Some(2),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(1),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-
-        // Test k larger than dataset
-        let query_vec = vec![0.0; config.embedding_dim];
-        let results = store.search_similar_texts(&query_vec, 100);
-        assert_eq!(results.len(), store.get_texts().len());
+    if let Some((start, end)) = source.char_range {
+        description += &format!(" [{}..{}]", start, end);
     }
+    description
+}
 
-    #[test]
-    fn test_empty_store_search() {
-        let store = NumpyGraphStore::new();
-        let query_vec = vec![0.0; 128];
-        
-        let text_results = store.search_similar_texts(&query_vec, 5);
-        assert!(text_results.is_empty());
+fn write_other_sources(out: &mut dyn Write, sources: &[SourceInfo]) -> std::io::Result<()> {
+    for source in sources {
+        writeln!(out, "   also in {}", describe_source(source))?;
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_zero_k_search() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Doc 1".to_string(),
-                source: SourceInfo {
-                    filename: "doc1.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-
-        let query_vec = vec![0.0; config.embedding_dim];
-        let results = store.search_similar_texts(&query_vec, 0);
-        assert!(results.is_empty());
+fn write_metadata(out: &mut dyn Write, metadata: &Metadata) -> std::io::Result<()> {
+    for (key, value) in metadata {
+        writeln!(out, "   {}={}", key, value)?;
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_monotonic_distances() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Doc 1".to_string(),
-                source: SourceInfo {
-                    filename: "doc1.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-            Document {
-                text: "Doc 2".to_string(),
-                source: SourceInfo {
-                    filename: "doc2.txt".to_string(),
-                    page_num: Some(2),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(1),
-                },
-            },
-            Document {
-                text: "Doc 3".to_string(),
-                source: SourceInfo {
-                    filename: "doc3.txt".to_string(),
-                    page_num: Some(3),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(2),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-
-        let query_vec = vec![0.0; config.embedding_dim];
-        let results = store.search_similar_texts(&query_vec, 5);
-
-        // Check distances are non-decreasing
-        for i in 0..results.len().saturating_sub(1) {
-            assert!(results[i].1 <= results[i + 1].1 + 1e-6);
+fn describe_path(store: &NumpyGraphStore, path: &RetrievalPath) -> String {
+    match path {
+        RetrievalPath::Vector { similarity } => format!("vector similarity {:.4}", similarity),
+        RetrievalPath::Keyword { keywords, weight } => {
+            let keywords: Vec<&str> = keywords.iter().map(|&id| store.get_keywords()[id].text.as_str()).collect();
+            format!("keywords {} ({:.4})", keywords.join(" -> "), weight)
         }
     }
+}
 
-    #[test]
-    fn test_finite_distances() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Doc 1".to_string(),
-                source: SourceInfo {
-                    filename: "doc1.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-
-        let query_vec = vec![0.0; config.embedding_dim];
-        let results = store.search_similar_texts(&query_vec, 5);
-
-        for (_, dist) in results {
-            assert!(dist.is_finite());
+/// Live texts, keywords, text-keyword weights and keyword edges as one JSON document
+fn export_graph(store: &NumpyGraphStore) -> serde_json::Value {
+    let live_texts = store.get_texts().iter().filter(|t| !store.is_text_removed(t.id));
+    let live_keywords: Vec<_> = store
+        .get_keywords()
+        .iter()
+        .filter(|kw| !store.is_keyword_removed(kw.id))
+        .collect();
+
+    let mut associations = Vec::new();
+    let mut edges = Vec::new();
+    for keyword in &live_keywords {
+        for text in store.get_keyword_related_texts(keyword.id, usize::MAX) {
+            let weight = store.get_association(text, keyword.id);
+            associations.push(serde_json::json!({ "text": text, "keyword": keyword.id, "weight": weight }));
+        }
+        for (other, weight) in store.get_adjacent_keywords(keyword.id, usize::MAX) {
+            if keyword.id < other {
+                edges.push(serde_json::json!({ "source": keyword.id, "target": other, "weight": weight }));
+            }
         }
     }
 
-    #[test]
-    fn test_duplicate_removal() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Unique text".to_string(),
-                source: SourceInfo {
-                    filename: "doc1.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-            Document {
-                text: "Unique text".to_string(), // Duplicate
-                source: SourceInfo {
-                    filename: "doc2.txt".to_string(),
-                    page_num: Some(2),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(1),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
+    serde_json::json!({
+        "model_id": store.model_id(),
+        "texts": live_texts.collect::<Vec<_>>(),
+        "keywords": live_keywords,
+        "associations": associations,
+        "keyword_edges": edges,
+    })
+}
 
-        // Should have removed duplicate
-        assert_eq!(store.get_texts().len(), 1);
-    }
+fn main() {
+    tracing_subscriber::fmt().with_writer(std::io::stderr).init();
 
-    #[test]
-    fn test_provenance_preserved() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Test document".to_string(),
-                source: SourceInfo {
-                    filename: "test.pdf".to_string(),
-                    page_num: Some(5),
-                    file_type: "pdf".to_string(),
-                    chunk_idx: Some(2),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-
-        let sources = store.get_sources();
-        assert_eq!(sources.len(), 1);
-        assert_eq!(sources[0].filename, "test.pdf");
-        assert_eq!(sources[0].page_num, Some(5));
-        assert_eq!(sources[0].file_type, "pdf");
-        assert_eq!(sources[0].chunk_idx, Some(2));
+    let cli = Cli::parse();
+    if let Err(e) = run(cli, &mut std::io::stdout().lock()) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
     }
+}
 
-    #[test]
-    fn test_keyword_related_texts_bounds() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Doc 1 with some words".to_string(),
-                source: SourceInfo {
-                    filename: "doc1.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-            Document {
-                text: "Doc 2 with different words".to_string(),
-                source: SourceInfo {
-                    filename: "doc2.txt".to_string(),
-                    page_num: Some(2),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(1),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-
-        // Test with k larger than available texts
-        if !store.get_keywords().is_empty() {
-            let keyword_idx = 0;
-            let results = store.get_keyword_related_texts(keyword_idx, 100);
-            assert!(results.len() <= store.get_texts().len());
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    #[test]
-    fn test_adjacent_keywords_bounds() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Doc 1".to_string(),
-                source: SourceInfo {
-                    filename: "doc1.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-
-        if !store.get_keywords().is_empty() {
-            let keyword_idx = 0;
-            let adjacent = store.get_adjacent_keywords(keyword_idx, 100);
-            // Should be bounded by available keywords (excluding self)
-            assert!(adjacent.len() <= store.get_keywords().len().saturating_sub(1));
-        }
+    fn run_args(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+        let cli = Cli::try_parse_from(std::iter::once("vectorized-kg").chain(args.iter().copied()))?;
+        let mut out = Vec::new();
+        run(cli, &mut out)?;
+        Ok(String::from_utf8(out)?)
     }
 
     #[test]
-    fn test_identical_queries_deterministic() {
-        let config = GraphConfig::default();
-        let documents = vec![
-            Document {
-                text: "Doc 1".to_string(),
-                source: SourceInfo {
-                    filename: "doc1.txt".to_string(),
-                    page_num: Some(1),
-                    file_type: "txt".to_string(),
-                    chunk_idx: Some(0),
-                },
-            },
-        ];
-        let mut store = NumpyGraphStore::new();
-        store.build_kg(&documents, &config);
-
-        let query_vec = vec![0.0; config.embedding_dim];
+    fn test_config_file_and_flags() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let toml_path = temp_dir.path().join("config.toml");
+        std::fs::write(&toml_path, "embedding_dim = 32\nk_neighbors = 4\n\n[keywords]\nstemming = true\n").unwrap();
+        let json_path = temp_dir.path().join("config.json");
+        std::fs::write(&json_path, r#"{"embedding_dim": 16}"#).unwrap();
+
+        let args = ConfigArgs {
+            config: Some(toml_path),
+            k_neighbors: Some(8),
+            use_hnsw: true,
+            keyphrases: Some(KeyphraseArg::Rake),
+            ..ConfigArgs::default()
+        };
+        let config = args.resolve().unwrap();
+        assert_eq!((config.embedding_dim, config.k_neighbors), (32, 8));
+        assert!(config.use_hnsw && config.keywords.stemming);
+        assert_eq!(config.keywords.keyphrases, KeyphraseMethod::Rake);
+        assert_eq!(config.trust_num, GraphConfig::default().trust_num);
 
-        // Run query multiple times
-        let results1 = store.search_similar_texts(&query_vec, 5);
-        let results2 = store.search_similar_texts(&query_vec, 5);
+        assert_eq!(read_config(&json_path).unwrap().embedding_dim, 16);
+    }
 
-        assert_eq!(results1.len(), results2.len());
-        for i in 0..results1.len() {
-            assert!((results1[i].1 - results2[i].1).abs() < 1e-6);
-        }
+    #[test]
+    fn test_ingest_query_and_inspect() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let docs = temp_dir.path().join("docs");
+        std::fs::create_dir(&docs).unwrap();
+        std::fs::write(docs.join("energy.txt"), "solar panels generate electricity\n\nelectricity powers trains").unwrap();
+        std::fs::write(docs.join("skip.bin"), [0u8, 159, 146, 150]).unwrap();
+        std::fs::write(docs.join("rows.jsonl"), "{\"body\": \"electric buses\", \"author\": \"Ada\"}\n").unwrap();
+        let graph = temp_dir.path().join("graph.vkg");
+        let graph = graph.to_str().unwrap();
+        let docs = docs.to_str().unwrap();
+
+        let chunking = ["--text-field", "body", "--max-tokens", "4", "--overlap", "0"];
+        let args = [&["--graph", graph, "ingest", docs, "--embedding-dim", "32"], &chunking[..]].concat();
+        let built = run_args(&args).unwrap();
+        assert!(built.contains("3 texts"));
+
+        std::fs::write(Path::new(docs).join("food.md"), "pasta recipes").unwrap();
+        std::fs::write(Path::new(docs).join("menu.md"), "pasta recipes").unwrap();
+        let added = run_args(&[&["--graph", graph, "ingest", docs], &chunking[..]].concat()).unwrap();
+        assert!(added.starts_with("Added 1 of 5 texts"));
+
+        let hits = run_args(&["--graph", graph, "query", "pasta recipes", "-k", "1"]).unwrap();
+        assert!(hits.contains("   also in menu.md #0 [0..13]"));
+        let hybrid = run_args(&["--graph", graph, "query", "recipes", "-k", "1", "--hybrid", "--vector-weight", "0"]).unwrap();
+        assert!(hybrid.contains("food.md #0"));
+        let filtered = run_args(&["--graph", graph, "query", "pasta recipes", "--file", "rows.jsonl"]).unwrap();
+        assert!(filtered.contains("rows.jsonl") && !filtered.contains("food.md"));
+        let by_author = run_args(&["--graph", graph, "query", "pasta recipes", "--meta", "author=Ada"]).unwrap();
+        assert!(by_author.starts_with("1. ") && by_author.contains("   author=Ada"));
+        assert!(!by_author.contains("energy.txt") && !by_author.contains("food.md"));
+        assert!(run_args(&["--graph", graph, "query", "pasta", "--meta", "author"]).is_err());
+        assert!(hits.contains("food.md #0 [0..13]") && hits.contains("pasta recipes"));
+
+        let wrong_embedder = ["--graph", graph, "query", "pasta", "--embedder", "command", "--embed-command", "cat"];
+        let error = run_args(&wrong_embedder).unwrap_err().to_string();
+        assert!(error.contains("built with hash-chacha8, got command:cat"), "{}", error);
+        assert!(run_args(&["--graph", graph, "query", "pasta", "--embedder", "command"]).is_err());
+
+        let retrieved = run_args(&["--graph", graph, "retrieve", "electricity", "-k", "2"]).unwrap();
+        assert!(retrieved.starts_with("1. ") && retrieved.contains("via "));
+
+        let keywords = run_args(&["--graph", graph, "keywords", "Electricity"]).unwrap();
+        assert!(keywords.contains("energy.txt p.1 #1 [35..60]") && keywords.contains("trains"));
+        assert!(run_args(&["--graph", graph, "keywords", "nothing"]).is_err());
+
+        std::fs::write(Path::new(docs).join("food-copy.md"), "Pasta recipes!").unwrap();
+        run_args(&[&["--graph", graph, "ingest", docs], &chunking[..]].concat()).unwrap();
+        let duplicates = run_args(&["--graph", graph, "duplicates"]).unwrap();
+        assert_eq!(duplicates.trim(), "[1.00] food.md #0 [0..13] ~ food-copy.md #0 [0..14]");
+
+        let stats = run_args(&["--graph", graph, "stats"]).unwrap();
+        assert!(stats.contains("5 live of 5") && stats.contains("embedding_dim = 32"));
+
+        let export: serde_json::Value =
+            serde_json::from_str(&run_args(&["--graph", graph, "export"]).unwrap()).unwrap();
+        assert_eq!(export["texts"].as_array().unwrap().len(), 5);
+        assert!(!export["keyword_edges"].as_array().unwrap().is_empty());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// User metadata of a document, such as author, URL or access labels, by key
+pub type Metadata = BTreeMap<String, MetadataValue>;
+
+/// Typed metadata value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataValue {
+    Text(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// Labels; a `Text` filter value matches any of them
+    List(Vec<String>),
+}
+
+impl MetadataValue {
+    /// Whether a filter asking for `wanted` accepts this value
+    pub fn matches(&self, wanted: &MetadataValue) -> bool {
+        match (self, wanted) {
+            (MetadataValue::List(labels), MetadataValue::Text(label)) => labels.contains(label),
+            _ => self.compare(wanted) == Some(Ordering::Equal),
+        }
+    }
+
+    /// Order between values of the same kind; integers and floats compare
+    /// numerically, anything else is unordered
+    pub fn compare(&self, other: &MetadataValue) -> Option<Ordering> {
+        match (self, other) {
+            (MetadataValue::Text(a), MetadataValue::Text(b)) => Some(a.cmp(b)),
+            (MetadataValue::Int(a), MetadataValue::Int(b)) => Some(a.cmp(b)),
+            (MetadataValue::Bool(a), MetadataValue::Bool(b)) => Some(a.cmp(b)),
+            (MetadataValue::List(a), MetadataValue::List(b)) => (a == b).then_some(Ordering::Equal),
+            (a, b) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            MetadataValue::Int(value) => Some(*value as f64),
+            MetadataValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataValue::Text(value) => write!(f, "{}", value),
+            MetadataValue::Int(value) => write!(f, "{}", value),
+            MetadataValue::Float(value) => write!(f, "{}", value),
+            MetadataValue::Bool(value) => write!(f, "{}", value),
+            MetadataValue::List(values) => write!(f, "[{}]", values.join(", ")),
+        }
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        MetadataValue::Text(value)
+    }
+}
+
+impl From<i64> for MetadataValue {
+    fn from(value: i64) -> Self {
+        MetadataValue::Int(value)
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        MetadataValue::Float(value)
+    }
+}
+
+impl From<bool> for MetadataValue {
+    fn from(value: bool) -> Self {
+        MetadataValue::Bool(value)
+    }
+}
+
+impl From<Vec<String>> for MetadataValue {
+    fn from(value: Vec<String>) -> Self {
+        MetadataValue::List(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_value_matching() {
+        let labels = MetadataValue::from(vec!["public".to_string(), "finance".to_string()]);
+        assert!(labels.matches(&"finance".into()));
+        assert!(!labels.matches(&"hr".into()));
+        assert!(MetadataValue::Int(3).matches(&MetadataValue::Float(3.0)));
+        assert!(!MetadataValue::Text("3".to_string()).matches(&MetadataValue::Int(3)));
+        assert_eq!(MetadataValue::Int(2).compare(&MetadataValue::Float(2.5)), Some(Ordering::Less));
+        assert_eq!(MetadataValue::from("2024-01-02").compare(&"2023-12-31".into()), Some(Ordering::Greater));
+        assert_eq!(MetadataValue::Bool(true).compare(&MetadataValue::Int(1)), None);
+        assert_eq!(labels.to_string(), "[public, finance]");
+    }
+}
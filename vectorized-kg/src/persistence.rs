@@ -0,0 +1,593 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::keywords::{KeywordEdges, NO_PREDECESSOR};
+use crate::legacy;
+use crate::dedup::LshIndex;
+use crate::lexical::LexicalIndex;
+use crate::store::keyword_lookup;
+use crate::{CsrMatrix, EmbeddingMatrix, GraphConfig, HnswIndex, KeywordNode, KgError, NumpyGraphStore, TextNode};
+
+/// File names inside a `save_mapped` directory
+const GRAPH_FILE: &str = "graph.vkg";
+const TEXT_VECTORS_FILE: &str = "texts.f32";
+const KEYWORD_VECTORS_FILE: &str = "keywords.f32";
+
+/// First bytes of every binary snapshot
+const MAGIC: &[u8; 8] = b"VKGSNAP\0";
+
+/// Binary layout version written by this build
+///
+/// Every change to the layout gets its own version:
+///
+/// 1. embeddings stored inside each node
+/// 2. embeddings moved to matrices
+/// 3. chunk character ranges
+/// 4. keyword settings
+/// 5. source tags
+/// 6. user metadata
+/// 7. merged duplicate sources
+/// 8. near-duplicate settings
+/// 9. metadata of each merged source
+/// 10. keyword edges stored per keyword, BM25 weights no longer stored
+///
+/// Every older version is still read (see `legacy`), with defaults for the
+/// fields added since. Newer versions are rejected.
+const FORMAT_VERSION: u32 = 10;
+
+/// Header flag: payload is deflate-compressed
+const FLAG_COMPRESSED: u32 = 1;
+
+/// Magic, version, flags, payload length and payload CRC32
+const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 4;
+
+/// On-disk encoding of a graph snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// Pretty-printed JSON, for debugging and diffing
+    Json,
+    /// Versioned bincode payload behind a magic header, with a CRC32 checksum
+    Binary { compressed: bool },
+}
+
+impl SnapshotFormat {
+    /// Pick a format from the file extension
+    ///
+    /// `.json` is JSON, `.vkgz` is compressed binary, anything else
+    /// (conventionally `.vkg`) is uncompressed binary.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => SnapshotFormat::Json,
+            Some(ext) if ext.eq_ignore_ascii_case("vkgz") => SnapshotFormat::Binary { compressed: true },
+            _ => SnapshotFormat::Binary { compressed: false },
+        }
+    }
+}
+
+/// Serialized form of `NumpyGraphStore`
+///
+/// Everything after `keywords` is optional so snapshots written before those
+/// fields existed still load. The embedding matrices are left out of the
+/// metadata file of a memory-mapped layout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct GraphStoreSnapshot {
+    pub(crate) texts: Vec<TextNode>,
+    pub(crate) keywords: Vec<KeywordNode>,
+    #[serde(default)]
+    pub(crate) text_vectors: Option<EmbeddingMatrix>,
+    #[serde(default)]
+    pub(crate) keyword_vectors: Option<EmbeddingMatrix>,
+    #[serde(default)]
+    pub(crate) config: Option<GraphConfig>,
+    #[serde(default)]
+    pub(crate) model_id: Option<String>,
+    #[serde(default)]
+    pub(crate) tf_mat: Option<CsrMatrix>,
+    #[serde(default)]
+    pub(crate) keyword_edges: Option<KeywordEdges>,
+    #[serde(default)]
+    pub(crate) pred_mat: Option<Array2<u32>>,
+    #[serde(default)]
+    pub(crate) removed_texts: Option<Vec<bool>>,
+    #[serde(default)]
+    pub(crate) removed_keywords: Option<Vec<bool>>,
+    #[serde(default)]
+    pub(crate) text_index: Option<HnswIndex>,
+    #[serde(default)]
+    pub(crate) keyword_index: Option<HnswIndex>,
+}
+
+pub(crate) fn write_snapshot(
+    path: &Path,
+    snapshot: &GraphStoreSnapshot,
+    format: SnapshotFormat,
+) -> Result<(), KgError> {
+    let bytes = match format {
+        SnapshotFormat::Json => serde_json::to_vec_pretty(snapshot).map_err(std::io::Error::other)?,
+        SnapshotFormat::Binary { compressed } => encode_binary(snapshot, compressed)?,
+    };
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Read a snapshot, detecting the format from its first bytes when `format` is `None`
+pub(crate) fn read_snapshot(
+    path: &Path,
+    format: Option<SnapshotFormat>,
+) -> Result<GraphStoreSnapshot, KgError> {
+    let bytes = std::fs::read(path)?;
+    let is_binary = match format {
+        Some(SnapshotFormat::Json) => false,
+        Some(SnapshotFormat::Binary { .. }) => true,
+        None => bytes.starts_with(MAGIC),
+    };
+    if is_binary {
+        decode_binary(&bytes)
+    } else {
+        let corrupt = |e: serde_json::Error| KgError::CorruptSnapshot(e.to_string());
+        let mut value: serde_json::Value = serde_json::from_slice(&bytes).map_err(corrupt)?;
+        upgrade_node_embeddings(&mut value);
+        serde_json::from_value(value).map_err(corrupt)
+    }
+}
+
+/// Move per-node `embedding` arrays of older JSON snapshots into matrices
+fn upgrade_node_embeddings(value: &mut serde_json::Value) {
+    for (nodes, matrix) in [("texts", "text_vectors"), ("keywords", "keyword_vectors")] {
+        if value.get(matrix).is_some() {
+            continue;
+        }
+        let Some(nodes) = value.get_mut(nodes).and_then(|n| n.as_array_mut()) else {
+            continue;
+        };
+        let mut dim = 0;
+        let mut data = Vec::new();
+        for node in nodes.iter_mut() {
+            if let Some(serde_json::Value::Array(embedding)) =
+                node.as_object_mut().and_then(|n| n.remove("embedding"))
+            {
+                dim = embedding.len();
+                data.extend(embedding);
+            }
+        }
+        if nodes.is_empty() || !data.is_empty() {
+            value[matrix] = serde_json::json!({ "dim": dim, "data": data });
+        }
+    }
+}
+
+fn encode_binary(snapshot: &GraphStoreSnapshot, compressed: bool) -> Result<Vec<u8>, KgError> {
+    let mut payload = bincode::serialize(snapshot).map_err(std::io::Error::other)?;
+    let mut flags = 0;
+    if compressed {
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&payload)?;
+        payload = encoder.finish()?;
+        flags |= FLAG_COMPRESSED;
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&flags.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<GraphStoreSnapshot, KgError> {
+    if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+        return Err(KgError::CorruptSnapshot("missing binary snapshot header".to_string()));
+    }
+    let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    let version = u32_at(8);
+    let flags = u32_at(12);
+    let payload_len = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+    let checksum = u32_at(24);
+
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(KgError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    if flags & !FLAG_COMPRESSED != 0 {
+        return Err(KgError::CorruptSnapshot(format!("unknown flags {:#x}", flags)));
+    }
+
+    let payload = &bytes[HEADER_LEN..];
+    if payload.len() as u64 != payload_len {
+        return Err(KgError::CorruptSnapshot(format!(
+            "header promises {} payload bytes, found {}",
+            payload_len,
+            payload.len()
+        )));
+    }
+    if crc32fast::hash(payload) != checksum {
+        return Err(KgError::CorruptSnapshot("checksum mismatch".to_string()));
+    }
+
+    let corrupt = |e: &dyn std::fmt::Display| KgError::CorruptSnapshot(e.to_string());
+    let mut decoded = Vec::new();
+    let payload = if flags & FLAG_COMPRESSED != 0 {
+        flate2::read::DeflateDecoder::new(payload)
+            .read_to_end(&mut decoded)
+            .map_err(|e| corrupt(&e))?;
+        &decoded[..]
+    } else {
+        payload
+    };
+    if version == FORMAT_VERSION {
+        bincode::deserialize(payload).map_err(|e| corrupt(&e))
+    } else {
+        legacy::decode(version, payload).map_err(|e| corrupt(&e))
+    }
+}
+
+impl NumpyGraphStore {
+    /// Save a snapshot, choosing the format from the file extension
+    /// (see `SnapshotFormat::from_path`)
+    pub fn save(&self, path: &str) -> Result<(), KgError> {
+        let path = std::path::Path::new(path);
+        write_snapshot(path, &self.to_snapshot(), SnapshotFormat::from_path(path))
+    }
+
+    /// Save a snapshot in an explicit format
+    pub fn save_as(&self, path: &str, format: SnapshotFormat) -> Result<(), KgError> {
+        write_snapshot(std::path::Path::new(path), &self.to_snapshot(), format)
+    }
+
+    /// Load a snapshot written by `save`, checking that its parts agree
+    ///
+    /// Binary snapshots are recognized by their header, anything else is read
+    /// as JSON. Snapshots that predate stored derived structures get them
+    /// rebuilt from the texts; the embedder must be reattached with
+    /// `set_embedder` before adding documents.
+    pub fn load(path: &str) -> Result<Self, KgError> {
+        Self::from_snapshot(read_snapshot(std::path::Path::new(path), None)?)
+    }
+
+    /// Load a snapshot in an explicit format
+    pub fn load_as(path: &str, format: SnapshotFormat) -> Result<Self, KgError> {
+        Self::from_snapshot(read_snapshot(std::path::Path::new(path), Some(format))?)
+    }
+
+    /// Save to directory `dir` in the memory-mappable layout
+    ///
+    /// Text and keyword embeddings go to `texts.f32` and `keywords.f32` as
+    /// contiguous aligned matrices; everything else goes to `graph.vkg`.
+    pub fn save_mapped(&self, dir: &str) -> Result<(), KgError> {
+        let dir = std::path::Path::new(dir);
+        std::fs::create_dir_all(dir)?;
+        self.text_vectors.write_to(&dir.join(TEXT_VECTORS_FILE))?;
+        self.keyword_vectors.write_to(&dir.join(KEYWORD_VECTORS_FILE))?;
+
+        let mut snapshot = self.to_snapshot();
+        snapshot.text_vectors = None;
+        snapshot.keyword_vectors = None;
+        write_snapshot(
+            &dir.join(GRAPH_FILE),
+            &snapshot,
+            SnapshotFormat::Binary { compressed: false },
+        )
+    }
+
+    /// Open a directory written by `save_mapped`, mapping the embeddings instead of reading them
+    ///
+    /// Searches read vectors straight from the mapping. The embedding files
+    /// must not be modified while the store is open; embeddings of documents
+    /// added later are kept on the heap, and compacting copies the kept rows there.
+    pub fn open_mapped(dir: &str) -> Result<Self, KgError> {
+        let dir = std::path::Path::new(dir);
+        let mut snapshot = read_snapshot(&dir.join(GRAPH_FILE), None)?;
+        snapshot.text_vectors = Some(EmbeddingMatrix::open(&dir.join(TEXT_VECTORS_FILE))?);
+        snapshot.keyword_vectors = Some(EmbeddingMatrix::open(&dir.join(KEYWORD_VECTORS_FILE))?);
+        Self::from_snapshot(snapshot)
+    }
+
+    pub(crate) fn to_snapshot(&self) -> GraphStoreSnapshot {
+        GraphStoreSnapshot {
+            texts: self.texts.to_vec(),
+            keywords: self.keywords.to_vec(),
+            text_vectors: Some(self.text_vectors.clone()),
+            keyword_vectors: Some(self.keyword_vectors.clone()),
+            config: Some(self.config.clone()),
+            model_id: self.model_id.clone(),
+            tf_mat: self.tf_mat.as_deref().cloned(),
+            keyword_edges: Some(KeywordEdges::clone(&self.keyword_edges)),
+            pred_mat: self.pred_mat.as_deref().cloned(),
+            removed_texts: Some(self.removed_texts.clone()),
+            removed_keywords: Some(self.removed_keywords.clone()),
+            text_index: self.text_index.as_deref().cloned(),
+            keyword_index: self.keyword_index.as_deref().cloned(),
+        }
+    }
+
+    fn from_snapshot(snapshot: GraphStoreSnapshot) -> Result<Self, KgError> {
+        let (n_texts, n_keywords) = (snapshot.texts.len(), snapshot.keywords.len());
+        let legacy = snapshot.tf_mat.is_none();
+        let keyword_edges = snapshot.keyword_edges;
+        let (text_vectors, keyword_vectors) = match (snapshot.text_vectors, snapshot.keyword_vectors) {
+            (Some(texts), Some(keywords)) => (texts, keywords),
+            _ => return Err(KgError::CorruptSnapshot("embeddings are missing".to_string())),
+        };
+        let config = match snapshot.config {
+            Some(config) => config,
+            // Older snapshots carry no config; infer the dimension from the vectors
+            None => GraphConfig {
+                embedding_dim: if text_vectors.is_empty() {
+                    keyword_vectors.dim()
+                } else {
+                    text_vectors.dim()
+                },
+                ..GraphConfig::default()
+            },
+        };
+
+        let mut store = Self {
+            texts: Arc::new(snapshot.texts),
+            keyword_ids: Arc::new(keyword_lookup(&snapshot.keywords)),
+            keywords: Arc::new(snapshot.keywords),
+            text_vectors,
+            keyword_vectors,
+            tf_mat: snapshot.tf_mat.map(Arc::new),
+            cooccurrence: Arc::default(),
+            keyword_edges: Arc::default(),
+            pred_mat: snapshot.pred_mat.map(Arc::new),
+            removed_texts: snapshot.removed_texts.unwrap_or_else(|| vec![false; n_texts]),
+            removed_keywords: snapshot.removed_keywords.unwrap_or_else(|| vec![false; n_keywords]),
+            lexical_index: LexicalIndex::default(),
+            near_index: Arc::new(LshIndex::new(&config.dedup)),
+            text_index: snapshot.text_index.map(Arc::new),
+            keyword_index: snapshot.keyword_index.map(Arc::new),
+            embedder: None,
+            model_id: snapshot.model_id,
+            config,
+        };
+
+        // Derived structures index into the stored ones, so those are checked first
+        store.validate()?;
+        if let Some(edges) = &keyword_edges {
+            edges
+                .check(n_keywords)
+                .map_err(|e| KgError::CorruptSnapshot(format!("keyword_edges: {}", e)))?;
+        }
+        store.lexical_index = LexicalIndex::build(store.texts.iter().map(|t| t.text.as_str()));
+        for id in (0..n_texts).filter(|&id| store.removed_texts[id]) {
+            store.lexical_index.remove(id);
+        }
+        store.rebuild_near_index();
+        if legacy {
+            store.update_term_frequencies(0);
+        } else {
+            store.recount_cooccurrences();
+        }
+        match keyword_edges {
+            Some(edges) => store.keyword_edges = Arc::new(edges.relink()),
+            // Versions before 10 stored the merged edges, which cannot be split by keyword
+            None => {
+                let config = store.config.clone();
+                store.build_keyword_edges(&config);
+                store.build_path_predecessors(&config);
+            }
+        }
+        Ok(store)
+    }
+
+    /// Check that every stored structure matches the node counts and embedding dimension
+    fn validate(&self) -> Result<(), KgError> {
+        let (n_texts, n_keywords) = (self.texts.len(), self.keywords.len());
+        let dim = self.config.embedding_dim;
+        let corrupt = |what: String| Err(KgError::CorruptSnapshot(what));
+
+        for (i, text) in self.texts.iter().enumerate() {
+            if text.id != i {
+                return corrupt(format!("text at position {} has id {}", i, text.id));
+            }
+        }
+        for (i, keyword) in self.keywords.iter().enumerate() {
+            if keyword.id != i {
+                return corrupt(format!("keyword at position {} has id {}", i, keyword.id));
+            }
+        }
+        if self.keyword_ids.len() != n_keywords {
+            return corrupt("duplicate keyword texts".to_string());
+        }
+
+        for (name, vectors, expected) in [
+            ("text", &self.text_vectors, n_texts),
+            ("keyword", &self.keyword_vectors, n_keywords),
+        ] {
+            if vectors.len() != expected {
+                return corrupt(format!("{} {} embeddings for {} nodes", vectors.len(), name, expected));
+            }
+            if vectors.dim() != dim && !vectors.is_empty() {
+                return corrupt(format!(
+                    "{} embeddings are {}-dimensional, config says {}",
+                    name,
+                    vectors.dim(),
+                    dim
+                ));
+            }
+            // Mapped rows were checked by `write_to`; reading them all here
+            // would page in the whole file on open
+            if vectors.is_mapped() {
+                continue;
+            }
+            if let Some(i) = (0..vectors.len()).find(|&i| !vectors.row(i).iter().all(|x| x.is_finite())) {
+                return Err(KgError::NanEmbedding(format!("stored embedding of {} {}", name, i)));
+            }
+        }
+
+        if let Some(tf_mat) = &self.tf_mat {
+            tf_mat.check().or_else(|e| corrupt(format!("tf_mat: {}", e)))?;
+            if tf_mat.nrows() != n_texts || tf_mat.ncols() != n_keywords {
+                return corrupt(format!(
+                    "tf_mat is {}x{}, expected {}x{}",
+                    tf_mat.nrows(),
+                    tf_mat.ncols(),
+                    n_texts,
+                    n_keywords
+                ));
+            }
+        }
+        if let Some(pred_mat) = &self.pred_mat {
+            if pred_mat.dim() != (n_keywords, n_keywords) {
+                return corrupt(format!(
+                    "pred_mat is {:?}, expected {}x{}",
+                    pred_mat.dim(),
+                    n_keywords,
+                    n_keywords
+                ));
+            }
+            if pred_mat.iter().any(|&p| p != NO_PREDECESSOR && p as usize >= n_keywords) {
+                return corrupt("pred_mat refers to a keyword that does not exist".to_string());
+            }
+        }
+
+        if self.removed_texts.len() != n_texts || self.removed_keywords.len() != n_keywords {
+            return corrupt("tombstone lists do not match node counts".to_string());
+        }
+        for (name, index, expected) in [
+            ("text index", &self.text_index, n_texts),
+            ("keyword index", &self.keyword_index, n_keywords),
+        ] {
+            if let Some(index) = index {
+                if index.len() != expected {
+                    return corrupt(format!("{} covers {} nodes, expected {}", name, index.len(), expected));
+                }
+                index.check().or_else(|e| corrupt(format!("{}: {}", name, e)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> GraphStoreSnapshot {
+        GraphStoreSnapshot {
+            texts: Vec::new(),
+            keywords: vec![KeywordNode {
+                id: 0,
+                text: "graph".to_string(),
+            }],
+            text_vectors: Some(EmbeddingMatrix::new(4)),
+            keyword_vectors: Some(vec![vec![0.5; 4]].into_iter().collect()),
+            config: Some(GraphConfig::default()),
+            model_id: Some("test".to_string()),
+            tf_mat: None,
+            keyword_edges: None,
+            pred_mat: Some(Array2::zeros((1, 1))),
+            removed_texts: Some(Vec::new()),
+            removed_keywords: Some(vec![false]),
+            text_index: None,
+            keyword_index: None,
+        }
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(SnapshotFormat::from_path(Path::new("g.json")), SnapshotFormat::Json);
+        assert_eq!(
+            SnapshotFormat::from_path(Path::new("g.vkgz")),
+            SnapshotFormat::Binary { compressed: true }
+        );
+        assert_eq!(
+            SnapshotFormat::from_path(Path::new("g.vkg")),
+            SnapshotFormat::Binary { compressed: false }
+        );
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        for compressed in [false, true] {
+            let bytes = encode_binary(&snapshot(), compressed).unwrap();
+            let decoded = decode_binary(&bytes).unwrap();
+            assert_eq!(decoded.keyword_vectors.unwrap().row(0), &[0.5; 4]);
+            assert_eq!(decoded.model_id.as_deref(), Some("test"));
+            assert_eq!(decoded.pred_mat, Some(Array2::zeros((1, 1))));
+        }
+    }
+
+    #[test]
+    fn test_binary_rejects_damage() {
+        let bytes = encode_binary(&snapshot(), false).unwrap();
+
+        let mut flipped = bytes.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 0xff;
+        assert!(decode_binary(&flipped).unwrap_err().to_string().contains("checksum"));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(decode_binary(truncated).is_err());
+
+        let mut future = bytes.clone();
+        future[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(decode_binary(&future).unwrap_err().to_string().contains("version"));
+        assert!(matches!(
+            decode_binary(&future),
+            Err(KgError::UnsupportedVersion { found, supported: FORMAT_VERSION }) if found == FORMAT_VERSION + 1
+        ));
+        assert!(matches!(decode_binary(truncated), Err(KgError::CorruptSnapshot(_))));
+    }
+
+    #[test]
+    fn test_load_older_versions() {
+        // Written by the builds that introduced each version, from the same
+        // four documents; the last two are duplicates, merged since version 7
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let temp_dir = tempfile::tempdir().unwrap();
+        for version in 1..FORMAT_VERSION {
+            let name = format!("v{}", version);
+            let path = fixtures.join(format!("graph-{}.vkg", name));
+            let bytes = std::fs::read(&path).unwrap();
+            assert_eq!(u32::from_le_bytes(bytes[8..12].try_into().unwrap()), version);
+
+            let mut store = NumpyGraphStore::load(path.to_str().unwrap()).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let texts = store.get_texts();
+            assert_eq!(texts.len(), 3, "{}", name);
+            assert_eq!(texts[0].source.filename, "doc1.txt");
+            assert_eq!(texts[2].source.char_range.is_some(), version >= 3, "{}", name);
+            assert_eq!(texts[0].source.tags.len(), usize::from(version >= 5), "{}", name);
+            assert_eq!(texts[0].metadata.len(), usize::from(version >= 6), "{}", name);
+            assert_eq!(texts[2].other_sources.len(), usize::from(version >= 7), "{}", name);
+            assert_eq!(store.config().embedding_dim, 4);
+            let source = texts[1].source.clone();
+
+            store
+                .set_embedder(std::sync::Arc::new(crate::HashEmbedder::new(4)))
+                .unwrap();
+            assert_eq!(
+                store.search_text("pasta recipes", 1).unwrap()[0].source.filename,
+                "doc3.txt"
+            );
+            let added = store
+                .add_documents(&[crate::Document {
+                    text: "electric trains".to_string(),
+                    source,
+                    metadata: Default::default(),
+                }])
+                .unwrap();
+            assert_eq!(added, vec![3]);
+
+            // Saving upgrades to the current version
+            let upgraded = temp_dir.path().join(format!("{}.vkg", name));
+            store.save(upgraded.to_str().unwrap()).unwrap();
+            assert_eq!(std::fs::read(&upgraded).unwrap()[8..12], FORMAT_VERSION.to_le_bytes());
+            assert_eq!(
+                NumpyGraphStore::load(upgraded.to_str().unwrap())
+                    .unwrap()
+                    .get_texts()
+                    .len(),
+                4
+            );
+        }
+    }
+}
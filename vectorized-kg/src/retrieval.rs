@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::search::{hit_locations, snippet};
+use crate::{Filter, KeywordExtractor, KgError, Metadata, NumpyGraphStore, SourceInfo};
+
+/// Options for `retrieve`
+///
+/// How widely the keyword graph is explored comes from the graph's own
+/// `GraphConfig`: each keyword follows its `k_neighbors` strongest edges and
+/// pulls in its `trust_num` most strongly associated texts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrievalConfig {
+    /// Texts taken directly from vector similarity to the query (positive similarity only)
+    pub seed_texts: usize,
+    /// Keywords closest to the query that start the graph expansion
+    pub seed_keywords: usize,
+    /// Keyword-graph hops from the seed keywords; 0 uses the seeds alone
+    pub hops: usize,
+    /// Factor applied to a keyword's score per hop away from its seed
+    pub hop_decay: f32,
+    /// Reciprocal rank fusion constant; larger values flatten rank differences
+    pub rrf_k: f32,
+    /// Hits returned
+    pub top_k: usize,
+    /// Only return texts whose source matches; keywords are expanded regardless
+    pub filter: Option<Filter>,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            seed_texts: 5,
+            seed_keywords: 5,
+            hops: 2,
+            hop_decay: 0.5,
+            rrf_k: 60.0,
+            top_k: 10,
+            filter: None,
+        }
+    }
+}
+
+/// How a retrieved text was reached
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RetrievalPath {
+    /// Among the texts closest to the query embedding
+    Vector { similarity: f32 },
+    /// Associated with the last of `keywords`; the first matched the query and
+    /// each following one is adjacent to the one before it
+    Keyword { keywords: Vec<usize>, weight: f32 },
+}
+
+/// Text returned by `retrieve`, with every path that surfaced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalHit {
+    pub id: usize,
+    /// Fused reciprocal-rank score, higher is better
+    pub score: f32,
+    pub text: String,
+    /// First location of the text that matches the filter, as in `TextHit`
+    pub source: SourceInfo,
+    pub snippet: String,
+    /// Metadata ingested with `source`
+    pub metadata: Metadata,
+    /// Further locations of the same text that match the filter
+    pub other_sources: Vec<SourceInfo>,
+    /// Vector path first, then keyword paths strongest first
+    pub paths: Vec<RetrievalPath>,
+}
+
+impl NumpyGraphStore {
+    /// Graph-augmented retrieval for a text query
+    ///
+    /// Seeds come from vector similarity, both texts and keywords. Seed keywords
+    /// are expanded through the keyword graph for `hops` hops, and the texts
+    /// associated with every reached keyword are scored by keyword score times
+    /// BM25 weight. The vector ranking and this graph ranking are fused with
+    /// reciprocal rank fusion.
+    pub fn retrieve(
+        &self,
+        query: &str,
+        config: &RetrievalConfig,
+    ) -> Result<Vec<RetrievalHit>, KgError> {
+        let query_vec = self.embed_query(query)?;
+        let accept = |i: usize| config.filter.as_ref().is_none_or(|f| f.matches(&self.texts[i]));
+        let mut paths: BTreeMap<usize, Vec<RetrievalPath>> = BTreeMap::new();
+
+        let vector_ranking: Vec<usize> = self
+            .search_texts_where(&query_vec, config.seed_texts, &accept)
+            .into_iter()
+            .filter(|&(_, distance)| distance < 1.0)
+            .map(|(id, distance)| {
+                paths
+                    .entry(id)
+                    .or_default()
+                    .push(RetrievalPath::Vector { similarity: 1.0 - distance });
+                id
+            })
+            .collect();
+
+        let seeds: Vec<(usize, f32)> = self
+            .search_keywords_where(&query_vec, config.seed_keywords)
+            .into_iter()
+            .map(|(id, distance)| (id, 1.0 - distance))
+            .collect();
+        let mut graph_scores: HashMap<usize, f32> = HashMap::new();
+        for (keywords, score) in self.expand_keywords(&seeds, config) {
+            let last = keywords[keywords.len() - 1];
+            let related = self.keyword_related_texts_where(last, self.config.trust_num, &accept);
+            let top_weight = related.first().map_or(0.0, |&id| self.get_association(id, last));
+            for id in related {
+                let weight = score * self.get_association(id, last) / top_weight;
+                *graph_scores.entry(id).or_insert(0.0) += weight;
+                paths.entry(id).or_default().push(RetrievalPath::Keyword {
+                    keywords: keywords.clone(),
+                    weight,
+                });
+            }
+        }
+        let mut graph_ranking: Vec<(usize, f32)> = graph_scores.into_iter().collect();
+        graph_ranking.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let graph_ranking: Vec<usize> = graph_ranking.into_iter().map(|(id, _)| id).collect();
+        let mut fused: BTreeMap<usize, f32> = BTreeMap::new();
+        for ranking in [vector_ranking, graph_ranking] {
+            for (rank, id) in ranking.into_iter().enumerate() {
+                *fused.entry(id).or_insert(0.0) += 1.0 / (config.rrf_k + rank as f32 + 1.0);
+            }
+        }
+        let mut fused: Vec<(usize, f32)> = fused.into_iter().collect();
+        fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        fused.truncate(config.top_k);
+
+        let extractor = KeywordExtractor::new(&self.config.keywords);
+        Ok(fused
+            .into_iter()
+            .map(|(id, score)| {
+                let mut paths = paths.remove(&id).unwrap_or_default();
+                paths.sort_by(|a, b| path_strength(b).total_cmp(&path_strength(a)));
+                let node = &self.texts[id];
+                let (source, metadata, other_sources) = hit_locations(node, config.filter.as_ref());
+                RetrievalHit {
+                    id,
+                    score,
+                    text: node.text.clone(),
+                    source,
+                    snippet: snippet(&node.text, query, &extractor),
+                    metadata,
+                    other_sources,
+                    paths,
+                }
+            })
+            .collect())
+    }
+
+    /// Best-scoring keyword chain to every keyword within `hops` of a seed
+    ///
+    /// A seed scores its query similarity; each hop multiplies by the edge
+    /// weight and `hop_decay`. Keywords with no positive score are dropped.
+    fn expand_keywords(&self, seeds: &[(usize, f32)], config: &RetrievalConfig) -> Vec<(Vec<usize>, f32)> {
+        let mut best: BTreeMap<usize, (Vec<usize>, f32)> = BTreeMap::new();
+        let mut frontier = Vec::new();
+        for &(id, similarity) in seeds {
+            if similarity > 0.0 {
+                best.insert(id, (vec![id], similarity));
+                frontier.push(id);
+            }
+        }
+
+        for _ in 0..config.hops {
+            let mut next = Vec::new();
+            frontier.sort_unstable();
+            frontier.dedup();
+            for x in frontier {
+                let (path, score) = best[&x].clone();
+                for (y, weight) in self.get_adjacent_keywords(x, self.config.k_neighbors) {
+                    let candidate = score * weight * config.hop_decay;
+                    if candidate > 0.0 && best.get(&y).is_none_or(|(_, s)| candidate > *s) {
+                        let mut path = path.clone();
+                        path.push(y);
+                        best.insert(y, (path, candidate));
+                        next.push(y);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        best.into_values().collect()
+    }
+}
+
+/// Sort key for the paths of a hit: the vector path first, then by weight
+fn path_strength(path: &RetrievalPath) -> f32 {
+    match path {
+        RetrievalPath::Vector { .. } => f32::INFINITY,
+        RetrievalPath::Keyword { weight, .. } => *weight,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Document, Embedder, GraphConfig};
+    use std::sync::Arc;
+
+    const VOCABULARY: [&str; 8] = ["solar", "panels", "generate", "electricity", "powers", "trains", "pasta", "recipes"];
+
+    /// Word counts over a fixed vocabulary, so related texts and keywords embed close together
+    struct BagOfWords;
+
+    impl Embedder for BagOfWords {
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    VOCABULARY
+                        .iter()
+                        .map(|word| text.split_whitespace().filter(|w| w == word).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+
+        fn dimension(&self) -> usize {
+            VOCABULARY.len()
+        }
+
+        fn model_id(&self) -> &str {
+            "bag-of-words"
+        }
+    }
+
+    fn store() -> NumpyGraphStore {
+        let config = GraphConfig {
+            embedding_dim: VOCABULARY.len(),
+            ..GraphConfig::default()
+        };
+        let documents: Vec<Document> = ["solar panels generate electricity", "electricity powers trains", "pasta recipes"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Document {
+                text: text.to_string(),
+                source: SourceInfo {
+                    filename: format!("doc{}.txt", i + 1),
+                    page_num: None,
+                    file_type: "txt".to_string(),
+                    chunk_idx: None,
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            })
+            .collect();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, Arc::new(BagOfWords)).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_retrieve_expands_through_keywords() {
+        let store = store();
+        let config = RetrievalConfig {
+            seed_texts: 1,
+            seed_keywords: 1,
+            hops: 0,
+            ..RetrievalConfig::default()
+        };
+        let hits = store.retrieve("solar", &config).unwrap();
+        let ids: Vec<usize> = hits.iter().map(|hit| hit.id).collect();
+        assert_eq!(ids, vec![0]);
+        let solar = store.keyword_id("solar").unwrap();
+        assert!(matches!(hits[0].paths[0], RetrievalPath::Vector { .. }));
+        assert!(matches!(&hits[0].paths[1], RetrievalPath::Keyword { keywords, .. } if keywords == &vec![solar]));
+
+        let hits = store.retrieve("solar", &RetrievalConfig { hops: 1, ..config }).unwrap();
+        let ids: Vec<usize> = hits.iter().map(|hit| hit.id).collect();
+        assert_eq!(ids, vec![0, 1]);
+        let electricity = store.keyword_id("electricity").unwrap();
+        assert_eq!(hits[1].paths.len(), 1);
+        match &hits[1].paths[0] {
+            RetrievalPath::Keyword { keywords, weight } => {
+                assert_eq!(keywords, &vec![solar, electricity]);
+                assert!(*weight > 0.0);
+            }
+            path => panic!("unexpected path {:?}", path),
+        }
+        assert!(hits[0].score > hits[1].score);
+    }
+
+    #[test]
+    fn test_retrieve_deduplicates_and_limits() {
+        let store = store();
+        let config = RetrievalConfig {
+            top_k: 2,
+            ..RetrievalConfig::default()
+        };
+        let hits = store.retrieve("electricity", &config).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_ne!(hits[0].id, hits[1].id);
+        assert!(hits.iter().all(|hit| hit.id != 2));
+        for hit in &hits {
+            let vector_paths = hit.paths.iter().filter(|p| matches!(p, RetrievalPath::Vector { .. })).count();
+            assert_eq!(vector_paths, 1);
+        }
+
+        let only_doc2 = RetrievalConfig {
+            filter: Some(Filter::filename("doc2.txt")),
+            ..RetrievalConfig::default()
+        };
+        let hits = store.retrieve("electricity", &only_doc2).unwrap();
+        assert_eq!(hits.iter().map(|hit| hit.id).collect::<Vec<_>>(), vec![1]);
+    }
+}
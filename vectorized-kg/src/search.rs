@@ -0,0 +1,377 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::chunker::token_spans;
+use crate::embedding::embed_checked;
+use crate::error::check_vector;
+use crate::{
+    Filter, GraphConfig, HnswIndex, KeywordExtractor, KgError, Metadata, NumpyGraphStore, SourceInfo, TextNode,
+    VectorSet,
+};
+
+/// Tokens kept in a snippet
+const SNIPPET_TOKENS: usize = 24;
+
+/// Tokens of context shown before the first query match in a snippet
+const SNIPPET_LEAD: usize = 8;
+
+/// Text returned by a text query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextHit {
+    pub id: usize,
+    /// Cosine similarity to the query, higher is closer
+    pub score: f32,
+    pub text: String,
+    /// First location of the text that matches the search filter, if any
+    pub source: SourceInfo,
+    /// Excerpt of `text` around the first word it shares with the query
+    pub snippet: String,
+    /// Metadata ingested with `source`
+    pub metadata: Metadata,
+    /// Further locations of the same text that match the search filter,
+    /// see `TextNode::other_sources`
+    pub other_sources: Vec<SourceInfo>,
+}
+
+/// Keyword returned by a text query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeywordHit {
+    pub id: usize,
+    /// Cosine similarity to the query, higher is closer
+    pub score: f32,
+    pub text: String,
+}
+
+impl NumpyGraphStore {
+    /// Insert nodes not yet in the HNSW indexes, creating them if enabled
+    pub(crate) fn update_indexes(&mut self, config: &GraphConfig) {
+        if !config.use_hnsw {
+            self.text_index = None;
+            self.keyword_index = None;
+            return;
+        }
+
+        let new_index = || Arc::new(HnswIndex::new(config.hnsw_m, config.hnsw_ef_construction, config.hnsw_ef_search));
+
+        let text_index = Arc::make_mut(self.text_index.get_or_insert_with(new_index));
+        while text_index.len() < self.texts.len() {
+            text_index.insert(&self.text_vectors);
+        }
+
+        let keyword_index = Arc::make_mut(self.keyword_index.get_or_insert_with(new_index));
+        while keyword_index.len() < self.keywords.len() {
+            keyword_index.insert(&self.keyword_vectors);
+        }
+    }
+
+    /// Nearest texts to `query_vec` by cosine distance, closest first
+    ///
+    /// Uses the HNSW index when one was built, otherwise an exact scan.
+    /// Removed texts are never returned, and neither is anything for a query
+    /// that is not a finite vector of the graph's dimension;
+    /// `try_search_similar_texts` reports such queries instead.
+    pub fn search_similar_texts(&self, query_vec: &[f32], k: usize) -> Vec<(usize, f32)> {
+        self.try_search_similar_texts(query_vec, k).unwrap_or_default()
+    }
+
+    /// `search_similar_texts`, failing on a query of the wrong dimension or with NaN
+    pub fn try_search_similar_texts(&self, query_vec: &[f32], k: usize) -> Result<Vec<(usize, f32)>, KgError> {
+        self.check_query(query_vec)?;
+        Ok(self.search_texts_where(query_vec, k, &|_| true))
+    }
+
+    /// Nearest texts whose source matches `filter`, closest first; nothing for an invalid query
+    pub fn search_similar_texts_filtered(&self, query_vec: &[f32], k: usize, filter: &Filter) -> Vec<(usize, f32)> {
+        self.try_search_similar_texts_filtered(query_vec, k, filter).unwrap_or_default()
+    }
+
+    /// `search_similar_texts_filtered`, failing on a query of the wrong dimension or with NaN
+    pub fn try_search_similar_texts_filtered(
+        &self,
+        query_vec: &[f32],
+        k: usize,
+        filter: &Filter,
+    ) -> Result<Vec<(usize, f32)>, KgError> {
+        self.check_query(query_vec)?;
+        Ok(self.search_texts_where(query_vec, k, &|i| filter.matches(&self.texts[i])))
+    }
+
+    /// Query vectors must match the stored embeddings
+    fn check_query(&self, query_vec: &[f32]) -> Result<(), KgError> {
+        check_vector(query_vec, self.config.embedding_dim, "query vector")
+    }
+
+    /// Nearest live texts accepted by `accept`; the filter is applied during the search
+    pub(crate) fn search_texts_where(
+        &self,
+        query_vec: &[f32],
+        k: usize,
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Vec<(usize, f32)> {
+        let accept = |i: usize| !self.removed_texts[i] && accept(i);
+        match &self.text_index {
+            Some(index) => {
+                let hits = index.search(query_vec, k, &self.text_vectors, &accept);
+                // Heavy removal or a selective filter can starve the graph walk; fall back to a scan
+                if hits.len() < k && hits.len() < (0..self.texts.len()).filter(|&i| accept(i)).count() {
+                    exact_search(&self.text_vectors, query_vec, k, &accept)
+                } else {
+                    hits
+                }
+            }
+            None => exact_search(&self.text_vectors, query_vec, k, &accept),
+        }
+    }
+
+    /// Nearest keywords to `query_vec` by cosine distance, closest first
+    ///
+    /// Uses the HNSW index when one was built, otherwise an exact scan.
+    /// Removed keywords are never returned, and nothing for an invalid query.
+    pub fn search_similar_keywords(&self, query_vec: &[f32], k: usize) -> Vec<(usize, f32)> {
+        self.try_search_similar_keywords(query_vec, k).unwrap_or_default()
+    }
+
+    /// `search_similar_keywords`, failing on a query of the wrong dimension or with NaN
+    pub fn try_search_similar_keywords(&self, query_vec: &[f32], k: usize) -> Result<Vec<(usize, f32)>, KgError> {
+        self.check_query(query_vec)?;
+        Ok(self.search_keywords_where(query_vec, k))
+    }
+
+    pub(crate) fn search_keywords_where(&self, query_vec: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let live = |i: usize| !self.removed_keywords[i];
+        match &self.keyword_index {
+            Some(index) => {
+                let hits = index.search(query_vec, k, &self.keyword_vectors, &live);
+                if hits.len() < k && hits.len() < self.live_keyword_count() {
+                    self.keywords_exact(query_vec, k)
+                } else {
+                    hits
+                }
+            }
+            None => self.keywords_exact(query_vec, k),
+        }
+    }
+
+    /// Texts closest to `query`, embedded with the graph's embedder
+    pub fn search_text(&self, query: &str, k: usize) -> Result<Vec<TextHit>, KgError> {
+        self.search_text_where(query, k, None)
+    }
+
+    /// Texts closest to `query` whose source matches `filter`
+    pub fn search_text_filtered(
+        &self,
+        query: &str,
+        k: usize,
+        filter: &Filter,
+    ) -> Result<Vec<TextHit>, KgError> {
+        self.search_text_where(query, k, Some(filter))
+    }
+
+    fn search_text_where(
+        &self,
+        query: &str,
+        k: usize,
+        filter: Option<&Filter>,
+    ) -> Result<Vec<TextHit>, KgError> {
+        let query_vec = self.embed_query(query)?;
+        let accept = |i: usize| filter.is_none_or(|f| f.matches(&self.texts[i]));
+        let hits = self
+            .search_texts_where(&query_vec, k, &accept)
+            .into_iter()
+            .map(|(id, distance)| (id, 1.0 - distance))
+            .collect();
+        Ok(self.text_hits(query, hits, filter))
+    }
+
+    /// `TextHit`s for scored text ids, with snippets for `query` and the
+    /// locations that match `filter`
+    pub(crate) fn text_hits(&self, query: &str, hits: Vec<(usize, f32)>, filter: Option<&Filter>) -> Vec<TextHit> {
+        let extractor = KeywordExtractor::new(&self.config.keywords);
+        hits.into_iter()
+            .map(|(id, score)| {
+                let node = &self.texts[id];
+                let (source, metadata, other_sources) = hit_locations(node, filter);
+                TextHit {
+                    id,
+                    score,
+                    text: node.text.clone(),
+                    source,
+                    snippet: snippet(&node.text, query, &extractor),
+                    metadata,
+                    other_sources,
+                }
+            })
+            .collect()
+    }
+
+    /// Keywords closest to `query`, embedded with the graph's embedder
+    pub fn search_keywords_text(&self, query: &str, k: usize) -> Result<Vec<KeywordHit>, KgError> {
+        let query_vec = self.embed_query(query)?;
+        Ok(self
+            .search_keywords_where(&query_vec, k)
+            .into_iter()
+            .map(|(id, distance)| KeywordHit {
+                id,
+                score: 1.0 - distance,
+                text: self.keywords[id].text.clone(),
+            })
+            .collect())
+    }
+
+    pub(crate) fn embed_query(&self, query: &str) -> Result<Vec<f32>, KgError> {
+        let embedder = self.embedder.as_ref().ok_or(KgError::MissingEmbedder)?;
+        Ok(embed_checked(embedder.as_ref(), &[query.to_string()])?.remove(0))
+    }
+
+    /// Brute-force text search; the recall baseline for the HNSW index
+    pub fn search_similar_texts_exact(&self, query_vec: &[f32], k: usize) -> Vec<(usize, f32)> {
+        if self.check_query(query_vec).is_err() {
+            return Vec::new();
+        }
+        exact_search(&self.text_vectors, query_vec, k, &|i| !self.removed_texts[i])
+    }
+
+    /// Brute-force keyword search; the recall baseline for the HNSW index
+    pub fn search_similar_keywords_exact(&self, query_vec: &[f32], k: usize) -> Vec<(usize, f32)> {
+        if self.check_query(query_vec).is_err() {
+            return Vec::new();
+        }
+        self.keywords_exact(query_vec, k)
+    }
+
+    pub(crate) fn keywords_exact(&self, query_vec: &[f32], k: usize) -> Vec<(usize, f32)> {
+        exact_search(&self.keyword_vectors, query_vec, k, &|i| !self.removed_keywords[i])
+    }
+}
+
+/// Rank every vector of `vectors` accepted by `filter` by cosine distance to `query_vec`
+fn exact_search<V: VectorSet + ?Sized>(
+    vectors: &V,
+    query_vec: &[f32],
+    k: usize,
+    filter: &dyn Fn(usize) -> bool,
+) -> Vec<(usize, f32)> {
+    if vectors.is_empty() {
+        return Vec::new();
+    }
+
+    let mut distances: Vec<(usize, f32)> = (0..vectors.len())
+        .filter(|&i| filter(i))
+        .map(|i| (i, cosine_distance_unchecked(query_vec, vectors.vector(i))))
+        .collect();
+
+    distances.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+    let k = std::cmp::min(k, distances.len());
+    distances.truncate(k);
+    distances
+}
+
+/// Source and metadata a hit on `node` is reported under, and its other
+/// sources: only the locations matching `filter` if there is one
+pub(crate) fn hit_locations(node: &TextNode, filter: Option<&Filter>) -> (SourceInfo, Metadata, Vec<SourceInfo>) {
+    let mut locations = node
+        .locations()
+        .filter(|&(source, metadata)| filter.is_none_or(|f| f.matches_at(source, metadata)));
+    // Hits passed the filter, so one location matches
+    let (source, metadata) = locations.next().unwrap_or((&node.source, &node.metadata));
+    let other_sources = locations.map(|(source, _)| source.clone()).collect();
+    (source.clone(), metadata.clone(), other_sources)
+}
+
+/// Up to `SNIPPET_TOKENS` tokens of `text`, starting shortly before the first
+/// word that matches a query word as a keyword; "..." marks cut-off ends
+pub(crate) fn snippet(text: &str, query: &str, extractor: &KeywordExtractor) -> String {
+    let query_words: Vec<String> = token_spans(query)
+        .into_iter()
+        .filter_map(|(from, to)| extractor.normalize(&query[from..to]))
+        .collect();
+    let spans = token_spans(text);
+    if spans.is_empty() {
+        return String::new();
+    }
+
+    let first_match = spans.iter().position(|&(from, to)| {
+        extractor
+            .normalize(&text[from..to])
+            .is_some_and(|word| query_words.contains(&word))
+    });
+    let start = first_match.map_or(0, |i| i.saturating_sub(SNIPPET_LEAD));
+    let end = (start + SNIPPET_TOKENS).min(spans.len());
+    let start = end.saturating_sub(SNIPPET_TOKENS);
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str("...");
+    }
+    snippet.push_str(&text[spans[start].0..spans[end - 1].1]);
+    if end < spans.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+/// Cosine distance between two vectors, from 0 (same direction) to 2
+///
+/// Fails if the lengths differ. A zero vector is at distance 1 from everything.
+pub fn cosine_distance(a: &[f32], b: &[f32]) -> Result<f32, KgError> {
+    if a.len() != b.len() {
+        return Err(KgError::DimensionMismatch {
+            expected: a.len(),
+            found: b.len(),
+        });
+    }
+    Ok(cosine_distance_unchecked(a, b))
+}
+
+/// `cosine_distance` for vectors already known to have the same length
+pub(crate) fn cosine_distance_unchecked(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut dot = 0.0;
+    let mut norm_a = 0.0;
+    let mut norm_b = 0.0;
+
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+
+    let norm_a = norm_a.sqrt();
+    let norm_b = norm_b.sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+
+    1.0 - dot / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_distance() {
+        let a = vec![1.0, 0.0, 0.0];
+        let b = vec![0.0, 1.0, 0.0];
+        let dist = cosine_distance(&a, &b).unwrap();
+        assert!((dist - 1.0).abs() < 1e-6);
+        assert!(matches!(
+            cosine_distance(&a, &[1.0, 0.0]),
+            Err(KgError::DimensionMismatch { expected: 3, found: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_snippet_centres_on_query_word() {
+        let extractor = KeywordExtractor::new(&crate::KeywordConfig::default());
+        let words: Vec<String> = (0..40).map(|i| format!("word{}", i)).collect();
+        let text = format!("{} Trains run {}", words[..20].join(" "), words[20..].join(" "));
+
+        let found = snippet(&text, "electric trains", &extractor);
+        assert!(found.starts_with("...word12 ") && found.ends_with("..."), "{}", found);
+        assert!(found.contains("Trains run"));
+        assert!(snippet(&text, "nothing", &extractor).starts_with("word0 word1 "));
+        assert_eq!(snippet("short text", "text", &extractor), "short text");
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use crate::error::KgError;
+use crate::store::{Document, NumpyGraphStore};
+
+/// A graph shared between reader threads and a writer
+///
+/// Readers take a `snapshot`, an immutable version of the graph they can
+/// query for as long as they hold it, however many batches are written in
+/// the meantime. Writers apply changes to a private copy of the latest
+/// version and publish it with a single pointer swap, so readers see either
+/// all of a batch or none of it and are only blocked for that swap.
+///
+/// Writers are serialized. The private copy shares every component with the
+/// version it was made from, and a change copies only the components it
+/// modifies, so a batch of removals leaves embeddings, indexes and term
+/// frequencies shared, and new embeddings are appended without copying the
+/// existing ones. Other components a batch modifies are still copied whole,
+/// so several changes made in one `update` pay for that once. Memory of
+/// replaced versions is freed once their last snapshot is dropped.
+#[derive(Debug)]
+pub struct SharedGraph {
+    current: RwLock<Version>,
+    /// Held for the whole of a write, so concurrent writers do not lose batches
+    writer: Mutex<()>,
+}
+
+#[derive(Debug)]
+struct Version {
+    number: u64,
+    graph: Arc<NumpyGraphStore>,
+}
+
+impl SharedGraph {
+    pub fn new(graph: NumpyGraphStore) -> Self {
+        Self {
+            current: RwLock::new(Version {
+                number: 0,
+                graph: Arc::new(graph),
+            }),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// The latest published version of the graph
+    pub fn snapshot(&self) -> Arc<NumpyGraphStore> {
+        // The published graph is never mutated in place, so a panic elsewhere
+        // cannot leave it half-written and a poisoned lock is still usable
+        self.current.read().unwrap_or_else(PoisonError::into_inner).graph.clone()
+    }
+
+    /// Number of versions published since `new`
+    pub fn version(&self) -> u64 {
+        self.current.read().unwrap_or_else(PoisonError::into_inner).number
+    }
+
+    /// Apply `change` to a copy of the latest version and publish the result
+    ///
+    /// Everything `change` does is published in a single version. If it
+    /// fails, nothing is published and the error is returned.
+    pub fn update<T>(
+        &self,
+        change: impl FnOnce(&mut NumpyGraphStore) -> Result<T, KgError>,
+    ) -> Result<T, KgError> {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut graph = NumpyGraphStore::clone(&self.snapshot());
+        let result = change(&mut graph)?;
+        self.publish(graph);
+        Ok(result)
+    }
+
+    /// Ingest a batch of documents and publish it; see `NumpyGraphStore::add_documents`
+    pub fn add_documents(&self, documents: &[Document]) -> Result<Vec<usize>, KgError> {
+        self.update(|graph| graph.add_documents(documents))
+    }
+
+    /// Publish `graph` in place of the latest version, e.g. one rebuilt or loaded from disk
+    pub fn replace(&self, graph: NumpyGraphStore) {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        self.publish(graph);
+    }
+
+    fn publish(&self, graph: NumpyGraphStore) {
+        let graph = Arc::new(graph);
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        current.number += 1;
+        // The old version is dropped here only if no reader still holds it
+        current.graph = graph;
+    }
+}
+
+impl From<NumpyGraphStore> for SharedGraph {
+    fn from(graph: NumpyGraphStore) -> Self {
+        Self::new(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::HashEmbedder;
+    use crate::store::{GraphConfig, SourceInfo};
+    use crate::Metadata;
+    use std::thread;
+
+    fn document(filename: &str, text: &str) -> Document {
+        Document {
+            text: text.to_string(),
+            source: SourceInfo {
+                filename: filename.to_string(),
+                page_num: None,
+                file_type: "txt".to_string(),
+                chunk_idx: Some(0),
+                char_range: None,
+                tags: Vec::new(),
+            },
+            metadata: Metadata::new(),
+        }
+    }
+
+    #[test]
+    fn test_snapshots_during_writes() {
+        let config = GraphConfig {
+            embedding_dim: 16,
+            ..GraphConfig::default()
+        };
+        let mut graph = NumpyGraphStore::new();
+        graph
+            .build_kg(
+                &[document("a.txt", "trains run on rails")],
+                &config,
+                Arc::new(HashEmbedder::new(16)),
+            )
+            .unwrap();
+        let shared = SharedGraph::new(graph);
+        let before = shared.snapshot();
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for batch in 0..10 {
+                    let documents: Vec<Document> = (0..3)
+                        .map(|i| document(&format!("{}.txt", batch), &format!("batch {} text {} about trains", batch, i)))
+                        .collect();
+                    shared.add_documents(&documents).unwrap();
+                }
+            });
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        // Batches are published whole: a snapshot never holds part of one
+                        let snapshot = shared.snapshot();
+                        assert_eq!((snapshot.get_texts().len() - 1) % 3, 0);
+                        assert_eq!(snapshot.text_vectors.len(), snapshot.get_texts().len());
+                        assert!(!snapshot.search_text("trains", 3).unwrap().is_empty());
+                    }
+                });
+            }
+        });
+
+        assert_eq!(shared.version(), 10);
+        assert_eq!(shared.snapshot().get_texts().len(), 31);
+        assert_eq!(before.get_texts().len(), 1);
+
+        let failed = shared.update(|graph| {
+            graph.remove_by_source("a.txt");
+            Err::<(), _>(KgError::InvalidInput("abandoned".to_string()))
+        });
+        assert!(failed.is_err());
+        assert_eq!(shared.version(), 10);
+        assert_eq!(shared.snapshot().live_text_count(), 31);
+
+        shared.replace(NumpyGraphStore::clone(&before));
+        assert_eq!(shared.version(), 11);
+        assert_eq!(shared.snapshot().get_texts().len(), 1);
+    }
+
+    #[test]
+    fn test_updates_share_unchanged_components() {
+        let config = GraphConfig {
+            embedding_dim: 16,
+            use_hnsw: true,
+            ..GraphConfig::default()
+        };
+        let mut graph = NumpyGraphStore::new();
+        let documents = [document("a.txt", "trains run on rails"), document("b.txt", "boats sail on water")];
+        graph.build_kg(&documents, &config, Arc::new(HashEmbedder::new(16))).unwrap();
+        let shared = SharedGraph::new(graph);
+        let before = shared.snapshot();
+
+        shared.update(|graph| Ok(graph.remove_by_source("b.txt"))).unwrap();
+        let after = shared.snapshot();
+        assert_eq!(after.live_text_count(), 1);
+        assert!(Arc::ptr_eq(&before.keywords, &after.keywords));
+        assert!(Arc::ptr_eq(before.tf_mat.as_ref().unwrap(), after.tf_mat.as_ref().unwrap()));
+        assert!(Arc::ptr_eq(before.text_index.as_ref().unwrap(), after.text_index.as_ref().unwrap()));
+        // The versions' own copies of what the removal changed
+        assert!(!Arc::ptr_eq(&before.cooccurrence, &after.cooccurrence));
+        assert_eq!(before.live_text_count(), 2);
+    }
+}
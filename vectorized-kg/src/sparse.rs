@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+
+/// Compressed sparse row matrix of `f32` values
+///
+/// Column indices within each row are kept sorted, so single entries can be
+/// looked up with a binary search.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CsrMatrix {
+    nrows: usize,
+    ncols: usize,
+    indptr: Vec<usize>,
+    indices: Vec<usize>,
+    data: Vec<f32>,
+}
+
+impl CsrMatrix {
+    /// Empty matrix with the given shape
+    pub fn zeros(nrows: usize, ncols: usize) -> Self {
+        Self {
+            nrows,
+            ncols,
+            indptr: vec![0; nrows + 1],
+            indices: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Build a matrix from per-row `(column, value)` entries
+    ///
+    /// Entries within a row may arrive in any order; duplicate columns are summed
+    /// and explicit zeros are dropped.
+    pub fn from_rows<I>(ncols: usize, rows: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<(usize, f32)>>,
+    {
+        let mut indptr = vec![0];
+        let mut indices = Vec::new();
+        let mut data = Vec::new();
+
+        for mut row in rows {
+            row.sort_by_key(|&(col, _)| col);
+            let start = indices.len();
+            for (col, value) in row {
+                assert!(col < ncols, "column {} out of bounds for {} columns", col, ncols);
+                if indices.len() > start && indices[indices.len() - 1] == col {
+                    let last = data.len() - 1;
+                    data[last] += value;
+                } else {
+                    indices.push(col);
+                    data.push(value);
+                }
+            }
+            // Drop entries that summed (or started) at zero
+            let mut keep = start;
+            for i in start..indices.len() {
+                if data[i] != 0.0 {
+                    indices[keep] = indices[i];
+                    data[keep] = data[i];
+                    keep += 1;
+                }
+            }
+            indices.truncate(keep);
+            data.truncate(keep);
+            indptr.push(indices.len());
+        }
+
+        Self {
+            nrows: indptr.len() - 1,
+            ncols,
+            indptr,
+            indices,
+            data,
+        }
+    }
+
+    /// Append rows built as in `from_rows`, widening the matrix to `ncols`
+    ///
+    /// Panics if `ncols` is smaller than the current column count.
+    pub fn append_rows<I>(&mut self, ncols: usize, rows: I)
+    where
+        I: IntoIterator<Item = Vec<(usize, f32)>>,
+    {
+        assert!(ncols >= self.ncols, "cannot shrink matrix from {} to {} columns", self.ncols, ncols);
+        let tail = Self::from_rows(ncols, rows);
+        let offset = self.indices.len();
+        self.indptr.extend(tail.indptr[1..].iter().map(|p| p + offset));
+        self.indices.extend(tail.indices);
+        self.data.extend(tail.data);
+        self.nrows += tail.nrows;
+        self.ncols = ncols;
+    }
+
+    pub fn nrows(&self) -> usize {
+        self.nrows
+    }
+
+    pub fn ncols(&self) -> usize {
+        self.ncols
+    }
+
+    /// Number of stored (non-zero) entries
+    pub fn nnz(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Column indices and values stored in row `i`
+    pub fn row(&self, i: usize) -> (&[usize], &[f32]) {
+        let range = self.indptr[i]..self.indptr[i + 1];
+        (&self.indices[range.clone()], &self.data[range])
+    }
+
+    /// Iterate the `(column, value)` entries of row `i`
+    pub fn row_iter(&self, i: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let (indices, data) = self.row(i);
+        indices.iter().copied().zip(data.iter().copied())
+    }
+
+    /// Value at `(i, j)`, zero if not stored
+    pub fn get(&self, i: usize, j: usize) -> f32 {
+        if i >= self.nrows {
+            return 0.0;
+        }
+        let (indices, data) = self.row(i);
+        match indices.binary_search(&j) {
+            Ok(pos) => data[pos],
+            Err(_) => 0.0,
+        }
+    }
+
+    /// Check the structure of a matrix that did not come from `from_rows`,
+    /// e.g. one deserialized from a snapshot
+    ///
+    /// Row pointers must start at zero, never decrease and end at the number
+    /// of entries, and column indices must be strictly increasing within each
+    /// row and below `ncols`. Every other method assumes this.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.indptr.len() != self.nrows + 1 {
+            return Err(format!("{} row pointers for {} rows", self.indptr.len(), self.nrows));
+        }
+        if self.indices.len() != self.data.len() {
+            return Err(format!("{} column indices for {} values", self.indices.len(), self.data.len()));
+        }
+        if self.indptr[0] != 0 || self.indptr[self.nrows] != self.indices.len() {
+            return Err(format!(
+                "row pointers span {}..{}, expected 0..{}",
+                self.indptr[0],
+                self.indptr[self.nrows],
+                self.indices.len()
+            ));
+        }
+        if let Some(i) = self.indptr.windows(2).position(|pair| pair[0] > pair[1]) {
+            return Err(format!("row pointers decrease at row {}", i));
+        }
+        for i in 0..self.nrows {
+            let (cols, _) = self.row(i);
+            if cols.windows(2).any(|pair| pair[0] >= pair[1]) {
+                return Err(format!("column indices of row {} are not strictly increasing", i));
+            }
+            if let Some(&col) = cols.last().filter(|&&col| col >= self.ncols) {
+                return Err(format!("row {} has column {} of {}", i, col, self.ncols));
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap rows and columns
+    pub fn transpose(&self) -> Self {
+        let mut counts = vec![0usize; self.ncols + 1];
+        for &col in &self.indices {
+            counts[col + 1] += 1;
+        }
+        for i in 0..self.ncols {
+            counts[i + 1] += counts[i];
+        }
+
+        let indptr = counts.clone();
+        let mut next = counts;
+        let mut indices = vec![0; self.nnz()];
+        let mut data = vec![0.0; self.nnz()];
+        for row in 0..self.nrows {
+            for (col, value) in self.row_iter(row) {
+                let dest = next[col];
+                indices[dest] = row;
+                data[dest] = value;
+                next[col] += 1;
+            }
+        }
+
+        Self {
+            nrows: self.ncols,
+            ncols: self.nrows,
+            indptr,
+            indices,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_rows_sums_duplicates() {
+        let m = CsrMatrix::from_rows(3, vec![vec![(2, 1.0), (0, 2.0), (2, 1.5)], vec![], vec![(1, 0.0)]]);
+        assert_eq!(m.nrows(), 3);
+        assert_eq!(m.nnz(), 2);
+        assert_eq!(m.row(0).0, &[0, 2]);
+        assert_eq!(m.get(0, 2), 2.5);
+        assert_eq!(m.get(2, 1), 0.0);
+    }
+
+    #[test]
+    fn test_transpose_roundtrip() {
+        let m = CsrMatrix::from_rows(4, vec![vec![(0, 1.0), (3, 2.0)], vec![(1, 3.0)], vec![(3, 4.0)]]);
+        let t = m.transpose();
+        assert_eq!((t.nrows(), t.ncols()), (4, 3));
+        assert_eq!(t.get(3, 2), 4.0);
+        assert_eq!(t.row(3).0, &[0, 2]);
+        assert_eq!(t.transpose(), m);
+    }
+
+    #[test]
+    fn test_check_rejects_bad_structure() {
+        let m = CsrMatrix::from_rows(3, vec![vec![(0, 1.0), (2, 2.0)], vec![(1, 3.0)]]);
+        assert!(m.check().is_ok());
+        assert!(CsrMatrix::zeros(2, 0).check().is_ok());
+
+        let mut out_of_range = m.clone();
+        out_of_range.indices[0] = 999_999;
+        assert!(out_of_range.check().is_err());
+        let mut unsorted = m.clone();
+        unsorted.indices.swap(0, 1);
+        assert!(unsorted.check().is_err());
+        let mut short = m.clone();
+        short.indptr[2] = 2;
+        assert!(short.check().is_err());
+        let mut decreasing = m.clone();
+        decreasing.indptr = vec![0, 4, 3];
+        assert!(decreasing.check().is_err());
+        let mut missing_rows = m;
+        missing_rows.nrows = 5;
+        assert!(missing_rows.check().is_err());
+    }
+
+    #[test]
+    fn test_append_rows_widens() {
+        let mut m = CsrMatrix::from_rows(2, vec![vec![(1, 1.0)]]);
+        m.append_rows(4, vec![vec![(3, 2.0)], vec![(0, 3.0)]]);
+        assert_eq!(m, CsrMatrix::from_rows(4, vec![vec![(1, 1.0)], vec![(3, 2.0)], vec![(0, 3.0)]]));
+    }
+}
@@ -0,0 +1,55 @@
+//! Built-in stopword lists, lowercase
+
+pub(crate) const ENGLISH: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "also", "am", "an", "and", "any", "are",
+    "as", "at", "be", "because", "been", "before", "being", "below", "between", "both", "but", "by",
+    "can", "could", "did", "do", "does", "doing", "down", "during", "each", "either", "else", "even",
+    "ever", "every", "few", "for", "from", "further", "had", "has", "have", "having", "he", "her",
+    "here", "hers", "herself", "him", "himself", "his", "how", "however", "i", "if", "in", "into", "is",
+    "it", "its", "itself", "just", "least", "less", "like", "many", "may", "me", "might", "more",
+    "most", "much", "must", "my", "myself", "neither", "no", "nor", "not", "now", "of", "off", "often",
+    "on", "once", "only", "or", "other", "ought", "our", "ours", "ourselves", "out", "over", "own",
+    "per", "rather", "same", "shall", "she", "should", "since", "so", "some", "such", "than", "that",
+    "the", "their", "theirs", "them", "themselves", "then", "there", "these", "they", "this", "those",
+    "though", "through", "thus", "to", "too", "under", "until", "up", "upon", "us", "very", "was", "we",
+    "were", "what", "when", "where", "whether", "which", "while", "who", "whom", "whose", "why", "will",
+    "with", "within", "without", "would", "yet", "you", "your", "yours", "yourself", "yourselves",
+];
+
+pub(crate) const FRENCH: &[&str] = &[
+    "à", "afin", "ai", "aie", "ainsi", "alors", "au", "aucun", "aucune", "aussi", "autre", "autres",
+    "aux", "avec", "avoir", "avait", "avant", "bien", "car", "ce", "ceci", "cela", "celle", "celles",
+    "celui", "ces", "cet", "cette", "ceux", "chaque", "chez", "comme", "comment", "dans", "de", "des",
+    "depuis", "donc", "dont", "du", "elle", "elles", "en", "encore", "entre", "es", "est", "et", "été",
+    "étaient", "était", "être", "eu", "eux", "fait", "il", "ils", "je", "la", "le", "les", "leur",
+    "leurs", "lui", "ma", "mais", "me", "même", "mes", "moi", "mon", "ne", "ni", "nos", "notre", "nous",
+    "on", "ont", "ou", "où", "par", "pas", "peu", "plus", "pour", "pourquoi", "quand", "que", "quel",
+    "quelle", "quelles", "quels", "qui", "sa", "sans", "se", "selon", "ses", "si", "son", "sont", "sous",
+    "sur", "ta", "te", "tes", "toi", "ton", "tous", "tout", "toute", "toutes", "très", "tu", "un",
+    "une", "vers", "vos", "votre", "vous",
+];
+
+pub(crate) const GERMAN: &[&str] = &[
+    "aber", "alle", "allem", "allen", "aller", "alles", "als", "also", "am", "an", "ander", "andere",
+    "anderen", "auch", "auf", "aus", "bei", "bin", "bis", "bist", "da", "damit", "dann", "das", "dass",
+    "dein", "deine", "dem", "den", "denn", "der", "des", "dessen", "die", "dies", "diese", "diesem",
+    "diesen", "dieser", "dieses", "doch", "dort", "du", "durch", "ein", "eine", "einem", "einen",
+    "einer", "eines", "er", "es", "etwas", "euch", "euer", "für", "gegen", "hab", "habe", "haben",
+    "hat", "hatte", "hier", "hin", "ich", "ihm", "ihn", "ihr", "ihre", "ihrem", "ihren", "im", "in",
+    "indem", "ist", "jede", "jedem", "jeden", "jeder", "jetzt", "kann", "kein", "keine", "man", "mein",
+    "meine", "mich", "mir", "mit", "muss", "nach", "nicht", "noch", "nun", "nur", "ob", "oder", "ohne",
+    "sehr", "sein", "seine", "sich", "sie", "sind", "so", "solche", "soll", "sondern", "über", "um",
+    "und", "uns", "unser", "unter", "viel", "vom", "von", "vor", "war", "waren", "was", "weil", "welche",
+    "wenn", "werden", "wie", "wieder", "will", "wir", "wird", "wo", "zu", "zum", "zur", "zwischen",
+];
+
+pub(crate) const SPANISH: &[&str] = &[
+    "a", "al", "algo", "algunos", "ante", "antes", "como", "con", "contra", "cual", "cuando", "de",
+    "del", "desde", "donde", "durante", "e", "el", "él", "ella", "ellas", "ellos", "en", "entre",
+    "era", "es", "esa", "esas", "ese", "eso", "esos", "esta", "está", "están", "estas", "este", "esto",
+    "estos", "fue", "fueron", "ha", "han", "hasta", "hay", "la", "las", "le", "les", "lo", "los", "más",
+    "me", "mi", "mis", "mucho", "muy", "nada", "ni", "no", "nos", "nosotros", "o", "otra", "otras",
+    "otro", "otros", "para", "pero", "poco", "por", "porque", "que", "qué", "quien", "se", "ser", "si",
+    "sí", "sin", "sobre", "son", "su", "sus", "también", "tanto", "te", "tiene", "tienen", "todo",
+    "todos", "tu", "tus", "un", "una", "uno", "unos", "y", "ya", "yo",
+];
@@ -0,0 +1,2309 @@
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::chunker::count_tokens;
+use crate::dedup::LshIndex;
+use crate::embedding::{embed_checked, Embedder};
+use crate::keywords::{Cooccurrence, KeywordEdges};
+use crate::lexical::LexicalIndex;
+use crate::{
+    CsrMatrix, DedupConfig, EmbeddingMatrix, HnswIndex, KeywordConfig, KeywordExtractor, KgError, Metadata,
+    NearDuplicateMode, NodeKind,
+};
+
+/// Represents a text node in the knowledge graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextNode {
+    pub id: usize,
+    pub text: String,
+    pub source: SourceInfo,
+    pub token_count: usize,
+    /// User metadata carried over from the `Document` found at `source`
+    #[serde(default)]
+    pub metadata: Metadata,
+    /// Where else the same text was ingested, in ingestion order; `source` is
+    /// where it was first seen
+    #[serde(default)]
+    pub other_sources: Vec<SourceInfo>,
+    /// Metadata of the document at each of `other_sources`, in the same order
+    ///
+    /// Sources past its end share `metadata`, as in snapshots written before
+    /// metadata was kept per source.
+    #[serde(default)]
+    pub other_metadata: Vec<Metadata>,
+}
+
+impl TextNode {
+    /// Every location of the text, `source` first
+    pub fn sources(&self) -> impl Iterator<Item = &SourceInfo> {
+        std::iter::once(&self.source).chain(&self.other_sources)
+    }
+
+    /// Every location of the text with the metadata it was ingested with, `source` first
+    pub fn locations(&self) -> impl Iterator<Item = (&SourceInfo, &Metadata)> {
+        let other_metadata = self.other_metadata.iter().chain(std::iter::repeat(&self.metadata));
+        std::iter::once((&self.source, &self.metadata)).chain(self.other_sources.iter().zip(other_metadata))
+    }
+
+    /// Record another location of the text, returning false if it is already known
+    fn add_source(&mut self, source: &SourceInfo, metadata: &Metadata) -> bool {
+        if self.sources().any(|known| known == source) {
+            return false;
+        }
+        self.fill_other_metadata();
+        self.other_sources.push(source.clone());
+        self.other_metadata.push(metadata.clone());
+        true
+    }
+
+    /// Forget every location in file `filename`, returning false if none is left
+    ///
+    /// The next location takes the place of `source` if it was in that file.
+    fn remove_file(&mut self, filename: &str) -> bool {
+        self.fill_other_metadata();
+        let (sources, metadata) = std::mem::take(&mut self.other_sources)
+            .into_iter()
+            .zip(std::mem::take(&mut self.other_metadata))
+            .filter(|(source, _)| source.filename != filename)
+            .unzip();
+        (self.other_sources, self.other_metadata) = (sources, metadata);
+        if self.source.filename != filename {
+            return true;
+        }
+        if self.other_sources.is_empty() {
+            return false;
+        }
+        self.source = self.other_sources.remove(0);
+        self.metadata = self.other_metadata.remove(0);
+        true
+    }
+
+    /// Give every one of `other_sources` its own metadata entry
+    fn fill_other_metadata(&mut self) {
+        self.other_metadata.resize(self.other_sources.len(), self.metadata.clone());
+    }
+}
+
+/// Source information with provenance
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct SourceInfo {
+    pub filename: String,
+    pub page_num: Option<u32>,
+    pub file_type: String,
+    pub chunk_idx: Option<usize>,
+    /// Start and end character offsets of the text in its file, end exclusive
+    #[serde(default)]
+    pub char_range: Option<(usize, usize)>,
+    /// Free-form labels attached at ingestion, matched by `Filter::Tag`
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Represents a keyword node in the knowledge graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeywordNode {
+    pub id: usize,
+    pub text: String,
+}
+
+/// In-memory graph store using ndarray for vector operations
+///
+/// Node metadata and embeddings are stored separately: row `i` of
+/// `text_vectors` is the embedding of text `i`, and likewise for keywords.
+///
+/// Components are held behind `Arc`, so a clone shares them all and a change
+/// copies (with `Arc::make_mut`) or replaces only the components it touches.
+/// The lexical index shares its postings between clones the same way.
+#[derive(Debug, Clone)]
+pub struct NumpyGraphStore {
+    pub(crate) texts: Arc<Vec<TextNode>>,
+    pub(crate) keywords: Arc<Vec<KeywordNode>>,
+    /// Keyword text -> id, kept in step with `keywords`
+    pub(crate) keyword_ids: Arc<HashMap<String, usize>>,
+    pub(crate) text_vectors: EmbeddingMatrix,
+    pub(crate) keyword_vectors: EmbeddingMatrix,
+    /// Texts x keywords raw term frequencies
+    pub(crate) tf_mat: Option<Arc<CsrMatrix>>,
+    /// Keyword postings and co-occurrence counts over live texts, kept in
+    /// step with `tf_mat`; BM25 association weights are computed from them
+    pub(crate) cooccurrence: Arc<Cooccurrence>,
+    /// Keyword graph edges
+    pub(crate) keyword_edges: Arc<KeywordEdges>,
+    /// Shortest-path predecessors over `keyword_edges`: `pred_mat[[src, dst]]` is the
+    /// keyword before `dst` on the path from `src`, or `NO_PREDECESSOR`
+    pub(crate) pred_mat: Option<Arc<Array2<u32>>>,
+    /// Tombstones: removed nodes keep their slot (and id) until `compact`
+    pub(crate) removed_texts: Vec<bool>,
+    pub(crate) removed_keywords: Vec<bool>,
+    /// BM25 index over the full text of every text node
+    pub(crate) lexical_index: LexicalIndex,
+    /// MinHash signatures of the live texts, for near-duplicate detection
+    pub(crate) near_index: Arc<LshIndex>,
+    /// Approximate nearest-neighbour indexes, present when built with `use_hnsw`
+    pub(crate) text_index: Option<Arc<HnswIndex>>,
+    pub(crate) keyword_index: Option<Arc<HnswIndex>>,
+    pub(crate) embedder: Option<Arc<dyn Embedder>>,
+    /// `model_id` of the embedder that produced the stored vectors
+    pub(crate) model_id: Option<String>,
+    /// Configuration the graph was last built with
+    pub(crate) config: GraphConfig,
+}
+
+impl Default for NumpyGraphStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NumpyGraphStore {
+    pub fn new() -> Self {
+        let config = GraphConfig::default();
+        Self {
+            texts: Arc::default(),
+            keywords: Arc::default(),
+            keyword_ids: Arc::default(),
+            text_vectors: EmbeddingMatrix::new(config.embedding_dim),
+            keyword_vectors: EmbeddingMatrix::new(config.embedding_dim),
+            tf_mat: None,
+            cooccurrence: Arc::default(),
+            keyword_edges: Arc::default(),
+            pred_mat: None,
+            removed_texts: Vec::new(),
+            removed_keywords: Vec::new(),
+            lexical_index: LexicalIndex::default(),
+            near_index: Arc::new(LshIndex::new(&config.dedup)),
+            text_index: None,
+            keyword_index: None,
+            embedder: None,
+            model_id: None,
+            config,
+        }
+    }
+
+    /// Build knowledge graph from documents
+    ///
+    /// Replaces any existing content. Fails if `config.embedding_dim` differs
+    /// from the dimension reported by `embedder`.
+    pub fn build_kg(
+        &mut self,
+        documents: &[Document],
+        config: &GraphConfig,
+        embedder: Arc<dyn Embedder>,
+    ) -> Result<(), KgError> {
+        tracing::info!("Building knowledge graph from {} documents...", documents.len());
+
+        if embedder.dimension() != config.embedding_dim {
+            return Err(KgError::DimensionMismatch {
+                expected: config.embedding_dim,
+                found: embedder.dimension(),
+            });
+        }
+
+        *self = Self::new();
+        self.text_vectors = EmbeddingMatrix::new(config.embedding_dim);
+        self.keyword_vectors = EmbeddingMatrix::new(config.embedding_dim);
+        self.model_id = Some(embedder.model_id().to_string());
+        self.embedder = Some(embedder);
+        self.config = config.clone();
+        self.near_index = Arc::new(LshIndex::new(&config.dedup));
+        self.add_documents(documents)?;
+        Ok(())
+    }
+
+    /// Add documents to an existing graph without rebuilding it
+    ///
+    /// Texts already in the graph (or repeated within `documents`) are not
+    /// added again; their sources and metadata are merged into the existing
+    /// node's `other_sources` and `other_metadata` instead. Only new texts and
+    /// keywords are embedded; new nodes get the next free ids, so existing ids
+    /// stay valid. Returns the ids of the added texts.
+    ///
+    /// Co-occurrence counts are updated with the new texts only. Only the
+    /// edges of keywords in the new texts are rescored, and only new keywords
+    /// are searched for similar ones; keyword paths are searched again only
+    /// near edges that changed. BM25 weights are computed when read.
+    pub fn add_documents(&mut self, documents: &[Document]) -> Result<Vec<usize>, KgError> {
+        let embedder = self.embedder.clone().ok_or(KgError::MissingEmbedder)?;
+
+        tracing::info!("Merging duplicate texts...");
+        let (documents, signatures) = self.merge_duplicates(documents);
+        tracing::info!("After deduplication: {} new texts", documents.len());
+
+        let texts: Vec<String> = documents.iter().map(|(d, _)| d.text.clone()).collect();
+
+        tracing::info!("Generating embeddings with {}...", embedder.model_id());
+        let vectors: Vec<Vec<f32>> = embed_checked(embedder.as_ref(), &texts)?;
+
+        tracing::info!("Extracting keywords...");
+        let mut new_keywords = self.extract_keywords(&texts);
+        new_keywords.retain(|kw| !self.keyword_ids.contains_key(kw));
+        tracing::info!("Extracted {} new keywords", new_keywords.len());
+
+        let keyvectors: Vec<Vec<f32>> = embed_checked(embedder.as_ref(), &new_keywords)?;
+
+        let first_text = self.texts.len();
+        Arc::make_mut(&mut self.texts).extend(documents.into_iter().enumerate().map(|(i, (doc, repeats))| TextNode {
+            id: first_text + i,
+            text: doc.text.clone(),
+            source: doc.source.clone(),
+            token_count: count_tokens(&doc.text),
+            metadata: doc.metadata.clone(),
+            other_sources: repeats.iter().map(|r| r.source.clone()).collect(),
+            other_metadata: repeats.iter().map(|r| r.metadata.clone()).collect(),
+        }));
+        for vector in &vectors {
+            self.text_vectors.push(vector);
+        }
+        for node in &self.texts[first_text..] {
+            self.lexical_index.push(&node.text);
+        }
+        Arc::make_mut(&mut self.near_index).append(signatures);
+
+        let first_keyword = self.keywords.len();
+        Arc::make_mut(&mut self.keyword_ids)
+            .extend(new_keywords.iter().enumerate().map(|(i, text)| (text.clone(), first_keyword + i)));
+        Arc::make_mut(&mut self.keywords).extend(
+            new_keywords
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| KeywordNode {
+                    id: first_keyword + i,
+                    text,
+                }),
+        );
+        for vector in &keyvectors {
+            self.keyword_vectors.push(vector);
+        }
+
+        self.removed_texts.resize(self.texts.len(), false);
+        self.removed_keywords.resize(self.keywords.len(), false);
+
+        let config = self.config.clone();
+        self.update_indexes(&config);
+
+        tracing::info!("Building keyword relationships...");
+        self.update_term_frequencies(first_text);
+        let mut touched = Vec::new();
+        let mut relinked: Vec<usize> = (first_keyword..self.keywords.len()).collect();
+        if let Some(tf_mat) = &self.tf_mat {
+            for i in first_text..self.texts.len() {
+                for (j, _) in tf_mat.row_iter(i) {
+                    touched.push(j);
+                    // Removed keywords that reappear in new texts come back under their old id
+                    if self.removed_keywords[j] {
+                        self.removed_keywords[j] = false;
+                        relinked.push(j);
+                    }
+                }
+            }
+        }
+        touched.sort_unstable();
+        touched.dedup();
+        let changed = self.update_keyword_edges(&config, &touched, &relinked, first_keyword);
+        self.update_path_predecessors(&config, &changed);
+
+        Ok((first_text..self.texts.len()).collect())
+    }
+
+    /// Embedder the graph was built with, if any
+    pub fn embedder(&self) -> Option<&Arc<dyn Embedder>> {
+        self.embedder.as_ref()
+    }
+
+    /// `model_id` of the embedder that produced the stored vectors
+    pub fn model_id(&self) -> Option<&str> {
+        self.model_id.as_deref()
+    }
+
+    /// Configuration the graph was built with
+    pub fn config(&self) -> &GraphConfig {
+        &self.config
+    }
+
+    /// Attach an embedder to a loaded graph so it can ingest more documents
+    ///
+    /// Fails if the embedder is not the model the graph was built with, or
+    /// produces vectors of a different dimension.
+    pub fn set_embedder(&mut self, embedder: Arc<dyn Embedder>) -> Result<(), KgError> {
+        if let Some(model_id) = &self.model_id {
+            if model_id != embedder.model_id() {
+                return Err(KgError::Embedder(format!(
+                    "graph was built with {}, got {}",
+                    model_id,
+                    embedder.model_id()
+                )));
+            }
+        }
+        if embedder.dimension() != self.config.embedding_dim {
+            return Err(KgError::DimensionMismatch {
+                expected: self.config.embedding_dim,
+                found: embedder.dimension(),
+            });
+        }
+        self.model_id = Some(embedder.model_id().to_string());
+        self.embedder = Some(embedder);
+        Ok(())
+    }
+
+    /// Documents whose text is not already live in the graph, each with its
+    /// later repeats within `documents` at other locations
+    ///
+    /// Repeats of live texts are merged into those nodes. Every location keeps
+    /// the metadata of the document found there. With
+    /// `NearDuplicateMode::Merge`, near-duplicates count as repeats of the
+    /// most similar earlier text. Also returns the signatures of the new
+    /// texts, keyed by the id each will get, for `near_index`.
+    fn merge_duplicates<'a>(
+        &mut self,
+        documents: &'a [Document],
+    ) -> (Vec<(&'a Document, Vec<&'a Document>)>, LshIndex) {
+        let live: HashMap<&str, usize> = self
+            .texts
+            .iter()
+            .filter(|t| !self.removed_texts[t.id])
+            .map(|t| (t.text.as_str(), t.id))
+            .collect();
+
+        // Near-duplicate candidates are the live texts and the new texts so far
+        let dedup = &self.config.dedup;
+        let mut batch = LshIndex::new(dedup);
+        let first_new = self.texts.len();
+
+        let mut unique: Vec<(&Document, Vec<&Document>)> = Vec::new();
+        let mut positions: HashMap<&str, usize> = HashMap::new();
+        let mut merged: Vec<(usize, &Document)> = Vec::new();
+        for doc in documents {
+            let mut duplicate_of = live
+                .get(doc.text.as_str())
+                .copied()
+                .or_else(|| positions.get(doc.text.as_str()).map(|&at| first_new + at));
+            if let (None, Some(signature)) = (duplicate_of, batch.signature(&doc.text)) {
+                if dedup.near_duplicates != NearDuplicateMode::Off {
+                    let mut near = self.near_index.near(&signature);
+                    near.extend(batch.near(&signature));
+                    near.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+                    if let Some(&(id, similarity)) = near.first() {
+                        if dedup.near_duplicates == NearDuplicateMode::Merge {
+                            duplicate_of = Some(id);
+                        } else {
+                            tracing::warn!(
+                                "Text from {} is a near-duplicate of text {} (similarity {:.2})",
+                                doc.source.filename,
+                                id,
+                                similarity
+                            );
+                        }
+                    }
+                }
+                if duplicate_of.is_none() {
+                    batch.insert(first_new + unique.len(), signature);
+                }
+            }
+
+            match duplicate_of {
+                Some(id) if id < first_new => merged.push((id, doc)),
+                Some(id) => {
+                    let (first, repeats) = &mut unique[id - first_new];
+                    if first.source != doc.source && repeats.iter().all(|r| r.source != doc.source) {
+                        repeats.push(doc);
+                    }
+                }
+                None => {
+                    positions.insert(&doc.text, unique.len());
+                    unique.push((doc, Vec::new()));
+                }
+            }
+        }
+
+        for (id, doc) in merged {
+            Arc::make_mut(&mut self.texts)[id].add_source(&doc.source, &doc.metadata);
+        }
+        (unique, batch)
+    }
+
+    /// Remove a text from the graph, returning false if it was already removed
+    ///
+    /// The node is tombstoned: it is excluded from every search and lookup but
+    /// keeps its id until `compact`. Keywords left without any live text are
+    /// removed too. BM25 weights follow the remaining live texts, and the
+    /// edges of the text's keywords are rescored over them.
+    pub fn remove_text(&mut self, id: usize) -> bool {
+        if id >= self.texts.len() || self.removed_texts[id] {
+            return false;
+        }
+        self.remove_texts(&[id]);
+        true
+    }
+
+    /// Forget file `filename` as a source of every text, returning how many texts were removed
+    ///
+    /// Texts also found in other files only lose this source, the next one
+    /// taking the place of `source` if needed; texts left without any source
+    /// are removed as by `remove_text`.
+    pub fn remove_by_source(&mut self, filename: &str) -> usize {
+        let found: Vec<usize> = (0..self.texts.len())
+            .filter(|&id| !self.removed_texts[id] && self.texts[id].sources().any(|s| s.filename == filename))
+            .collect();
+        let mut ids = Vec::new();
+        for id in found {
+            if !Arc::make_mut(&mut self.texts)[id].remove_file(filename) {
+                ids.push(id);
+            }
+        }
+        self.remove_texts(&ids);
+        ids.len()
+    }
+
+    fn remove_texts(&mut self, ids: &[usize]) {
+        if ids.is_empty() {
+            return;
+        }
+        for &id in ids {
+            self.removed_texts[id] = true;
+            self.lexical_index.remove(id);
+            Arc::make_mut(&mut self.near_index).remove(id);
+        }
+
+        // Keywords of the removed texts, and those no live text still mentions
+        let mut touched = Vec::new();
+        let mut relinked = Vec::new();
+        if let Some(tf_mat) = &self.tf_mat {
+            let cooccurrence = Arc::make_mut(&mut self.cooccurrence);
+            for &id in ids {
+                let row = tf_mat.row(id);
+                cooccurrence.remove(id, row, self.texts[id].token_count);
+                for &j in row.0 {
+                    touched.push(j);
+                    if cooccurrence.doc_freq(j) == 0 && !self.removed_keywords[j] {
+                        self.removed_keywords[j] = true;
+                        relinked.push(j);
+                    }
+                }
+            }
+        }
+        touched.sort_unstable();
+        touched.dedup();
+
+        let config = self.config.clone();
+        let changed = self.update_keyword_edges(&config, &touched, &relinked, self.keywords.len());
+        self.update_path_predecessors(&config, &changed);
+    }
+
+    /// Whether text `id` has been removed and awaits compaction
+    pub fn is_text_removed(&self, id: usize) -> bool {
+        self.removed_texts.get(id).copied().unwrap_or(false)
+    }
+
+    /// Whether keyword `id` has been removed and awaits compaction
+    pub fn is_keyword_removed(&self, id: usize) -> bool {
+        self.removed_keywords.get(id).copied().unwrap_or(false)
+    }
+
+    /// Number of texts that have not been removed
+    pub fn live_text_count(&self) -> usize {
+        self.removed_texts.iter().filter(|&&removed| !removed).count()
+    }
+
+    /// Number of keywords that have not been removed
+    pub fn live_keyword_count(&self) -> usize {
+        self.removed_keywords.iter().filter(|&&removed| !removed).count()
+    }
+
+    /// Drop removed nodes and renumber the rest densely, preserving order
+    ///
+    /// Derived structures and search indexes are rebuilt from the stored
+    /// embeddings, and keyword edges keep their weights under the new ids;
+    /// nothing is re-embedded. Returns the old-to-new id mapping.
+    pub fn compact(&mut self) -> Compaction {
+        let text_ids = renumber(&self.removed_texts);
+        let keyword_ids = renumber(&self.removed_keywords);
+        let n_keywords = keyword_ids.iter().flatten().count();
+
+        let tf_rows: Vec<Vec<(usize, f32)>> = match &self.tf_mat {
+            Some(tf_mat) => (0..self.texts.len())
+                .filter(|&i| text_ids[i].is_some())
+                .map(|i| {
+                    tf_mat
+                        .row_iter(i)
+                        .filter_map(|(j, tf)| keyword_ids[j].map(|j| (j, tf)))
+                        .collect()
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        self.texts = Arc::new(
+            Arc::unwrap_or_clone(std::mem::take(&mut self.texts))
+                .into_iter()
+                .filter_map(|mut node| {
+                    node.id = text_ids[node.id]?;
+                    Some(node)
+                })
+                .collect(),
+        );
+        self.keywords = Arc::new(
+            Arc::unwrap_or_clone(std::mem::take(&mut self.keywords))
+                .into_iter()
+                .filter_map(|mut node| {
+                    node.id = keyword_ids[node.id]?;
+                    Some(node)
+                })
+                .collect(),
+        );
+        self.keyword_ids = Arc::new(keyword_lookup(&self.keywords));
+        self.keyword_edges = Arc::new(self.keyword_edges.renumber(&keyword_ids));
+        self.lexical_index = LexicalIndex::build(self.texts.iter().map(|t| t.text.as_str()));
+        self.near_index = Arc::new(self.near_index.renumber(&text_ids));
+        let live = |removed: &[bool]| removed.iter().map(|&gone| !gone).collect::<Vec<_>>();
+        self.text_vectors.retain_rows(&live(&self.removed_texts));
+        self.keyword_vectors.retain_rows(&live(&self.removed_keywords));
+        self.removed_texts = vec![false; self.texts.len()];
+        self.removed_keywords = vec![false; self.keywords.len()];
+        self.tf_mat = Some(Arc::new(CsrMatrix::from_rows(n_keywords, tf_rows)));
+        self.recount_cooccurrences();
+
+        let config = self.config.clone();
+        self.text_index = None;
+        self.keyword_index = None;
+        self.update_indexes(&config);
+        self.build_path_predecessors(&config);
+
+        Compaction { text_ids, keyword_ids }
+    }
+
+    /// Embedding of text `id`; panics if `id` is out of range
+    pub fn text_embedding(&self, id: usize) -> &[f32] {
+        self.text_vectors.row(id)
+    }
+
+    /// Embedding of keyword `id`; panics if `id` is out of range
+    pub fn keyword_embedding(&self, id: usize) -> &[f32] {
+        self.keyword_vectors.row(id)
+    }
+
+    pub fn get_texts(&self) -> &[TextNode] {
+        &self.texts
+    }
+
+    pub fn get_keywords(&self) -> &[KeywordNode] {
+        &self.keywords
+    }
+
+    /// Text `id`, removed or not
+    pub fn text(&self, id: usize) -> Result<&TextNode, KgError> {
+        self.texts.get(id).ok_or(KgError::IndexOutOfRange {
+            kind: NodeKind::Text,
+            index: id,
+            len: self.texts.len(),
+        })
+    }
+
+    /// Keyword `id`, removed or not
+    pub fn keyword(&self, id: usize) -> Result<&KeywordNode, KgError> {
+        self.keywords.get(id).ok_or(KgError::IndexOutOfRange {
+            kind: NodeKind::Keyword,
+            index: id,
+            len: self.keywords.len(),
+        })
+    }
+
+    /// Id of the live keyword whose text is exactly `keyword`
+    pub fn keyword_id(&self, keyword: &str) -> Option<usize> {
+        self.keyword_ids
+            .get(keyword)
+            .copied()
+            .filter(|&id| !self.removed_keywords[id])
+    }
+
+    /// Id of the live keyword matching a free-form term such as "Solar Panels"
+    ///
+    /// The term is normalized like extracted keywords (case, stopwords and,
+    /// if configured, stemming) before the lookup.
+    pub fn find_keyword(&self, term: &str) -> Option<usize> {
+        let keyword = KeywordExtractor::new(&self.config.keywords).normalize(term)?;
+        self.keyword_id(&keyword)
+    }
+
+    pub fn get_sources(&self) -> Vec<&SourceInfo> {
+        self.texts.iter().map(|t| &t.source).collect::<Vec<_>>()
+    }
+
+    /// Node and edge counts of the graph
+    pub fn stats(&self) -> GraphStats {
+        let live_keyword_edges = self.keyword_edges.count(|x| !self.removed_keywords[x]);
+        // Postings only hold live texts, and a keyword in one is live
+        let live_associations = (0..self.keywords.len()).map(|j| self.cooccurrence.doc_freq(j)).sum();
+        GraphStats {
+            texts: self.texts.len(),
+            live_texts: self.live_text_count(),
+            keywords: self.keywords.len(),
+            live_keywords: self.live_keyword_count(),
+            associations: live_associations,
+            keyword_edges: live_keyword_edges,
+        }
+    }
+}
+
+/// Keyword text -> id map of `keywords`
+pub(crate) fn keyword_lookup(keywords: &[KeywordNode]) -> HashMap<String, usize> {
+    keywords.iter().map(|kw| (kw.text.clone(), kw.id)).collect()
+}
+
+/// New id of every slot that survives compaction, `None` for removed ones
+fn renumber(removed: &[bool]) -> Vec<Option<usize>> {
+    let mut next = 0;
+    removed
+        .iter()
+        .map(|&gone| {
+            if gone {
+                None
+            } else {
+                next += 1;
+                Some(next - 1)
+            }
+        })
+        .collect()
+}
+
+/// Id mapping produced by `NumpyGraphStore::compact`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compaction {
+    /// New id of each old text id, `None` if the text was removed
+    pub text_ids: Vec<Option<usize>>,
+    /// New id of each old keyword id, `None` if the keyword was removed
+    pub keyword_ids: Vec<Option<usize>>,
+}
+
+/// Counts reported by `NumpyGraphStore::stats`
+///
+/// Edge counts only include edges between live nodes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphStats {
+    /// Text slots, including removed texts awaiting compaction
+    pub texts: usize,
+    pub live_texts: usize,
+    /// Keyword slots, including removed keywords awaiting compaction
+    pub keywords: usize,
+    pub live_keywords: usize,
+    /// Non-zero text-keyword BM25 weights
+    pub associations: usize,
+    /// Undirected keyword-keyword edges
+    pub keyword_edges: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub text: String,
+    pub source: SourceInfo,
+    /// User metadata such as author, URL or access labels; searchable with `Filter::Metadata`
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphConfig {
+    pub embedding_dim: usize,
+    pub k_neighbors: usize,
+    pub trust_num: usize,
+    pub negative_multiplier: usize,
+    pub connect_threshold: f32,
+    /// BM25 term-frequency saturation for text-keyword weights
+    pub bm25_k1: f32,
+    /// BM25 document-length normalization for text-keyword weights
+    pub bm25_b: f32,
+    /// Also link keywords whose embeddings lie within `connect_threshold` cosine distance
+    pub similarity_edges: bool,
+    /// Longest keyword path, in edges, that `keyword_path` will find
+    pub max_path_hops: usize,
+    /// Largest keyword count for which all-pairs predecessors are precomputed
+    pub max_pred_keywords: usize,
+    /// Build HNSW indexes for approximate search instead of scanning every node
+    pub use_hnsw: bool,
+    /// HNSW links per node (doubled on the bottom layer)
+    pub hnsw_m: usize,
+    /// HNSW candidate list size while inserting
+    pub hnsw_ef_construction: usize,
+    /// HNSW candidate list size while searching
+    pub hnsw_ef_search: usize,
+    /// How keywords are extracted from texts
+    pub keywords: KeywordConfig,
+    /// Near-duplicate detection while adding documents
+    pub dedup: DedupConfig,
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        Self {
+            embedding_dim: 768,
+            k_neighbors: 30,
+            trust_num: 5,
+            negative_multiplier: 7,
+            connect_threshold: 0.2,
+            bm25_k1: 1.2,
+            bm25_b: 0.75,
+            similarity_edges: false,
+            max_path_hops: 4,
+            max_pred_keywords: 2048,
+            use_hnsw: false,
+            hnsw_m: 16,
+            hnsw_ef_construction: 200,
+            hnsw_ef_search: 64,
+            keywords: KeywordConfig::default(),
+            dedup: DedupConfig::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Filter, Fusion, HashEmbedder, HybridConfig, KeyphraseMethod, MetadataValue, RetrievalConfig, SnapshotFormat,
+    };
+
+    fn hash_embedder(config: &GraphConfig) -> Arc<dyn Embedder> {
+        Arc::new(HashEmbedder::new(config.embedding_dim))
+    }
+
+    #[test]
+    fn test_basic_graph() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Hello world".to_string(),
+                source: SourceInfo {
+                    filename: "test.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+        assert!(!store.get_texts().is_empty());
+    }
+
+    #[test]
+    fn test_embedder_dimension_mismatch() {
+        let config = GraphConfig::default();
+        let documents = vec![Document {
+            text: "Hello world".to_string(),
+            source: SourceInfo {
+                filename: "test.txt".to_string(),
+                page_num: Some(1),
+                file_type: "txt".to_string(),
+                chunk_idx: Some(0),
+                char_range: None,
+                tags: Vec::new(),
+            },
+            metadata: Metadata::new(),
+        }];
+        let mut store = NumpyGraphStore::new();
+        let embedder = Arc::new(HashEmbedder::new(config.embedding_dim / 2));
+        assert!(store.build_kg(&documents, &config, embedder).is_err());
+        assert!(store.get_texts().is_empty());
+    }
+
+    #[test]
+    fn test_hash_embedder_deterministic() {
+        let embedder = HashEmbedder::new(16);
+        let texts = vec!["alpha".to_string(), "beta".to_string()];
+        let a = embedder.embed(&texts).unwrap();
+        let b = embedder.embed(&texts).unwrap();
+        assert_eq!(a, b);
+        assert!(a.iter().all(|v| v.len() == embedder.dimension()));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let config = GraphConfig::default();
+        let documents = vec![Document {
+            text: "Test document".to_string(),
+            source: SourceInfo {
+                filename: "test.txt".to_string(),
+                page_num: Some(1),
+                file_type: "txt".to_string(),
+                chunk_idx: Some(0),
+                char_range: None,
+                tags: Vec::new(),
+            },
+            metadata: Metadata::new(),
+        }];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("graph.json");
+        store.save(path.to_str().unwrap()).unwrap();
+        let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(store.get_texts().len(), loaded.get_texts().len());
+    }
+
+    #[test]
+    fn test_save_load_preserves_derived_structures() {
+        let config = GraphConfig {
+            bm25_k1: 1.5,
+            ..GraphConfig::default()
+        };
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        store.remove_text(2);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("graph.json");
+        store.save(path.to_str().unwrap()).unwrap();
+        let mut loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(loaded.config().bm25_k1, 1.5);
+        assert_eq!(loaded.model_id(), Some("hash-chacha8"));
+        assert!(loaded.is_text_removed(2));
+        assert_eq!(loaded.text_embedding(2), store.text_embedding(2));
+        for keyword in store.get_keywords() {
+            assert_eq!(
+                loaded.get_keyword_related_texts(keyword.id, 10),
+                store.get_keyword_related_texts(keyword.id, 10)
+            );
+            assert_eq!(
+                loaded.get_adjacent_keywords(keyword.id, 10),
+                store.get_adjacent_keywords(keyword.id, 10)
+            );
+            assert_eq!(loaded.keyword_path(0, keyword.id), store.keyword_path(0, keyword.id));
+        }
+
+        assert!(loaded.add_documents(&path_documents()).is_err());
+        assert!(loaded.set_embedder(Arc::new(HashEmbedder::new(16))).is_err());
+        loaded.set_embedder(hash_embedder(&config)).unwrap();
+        assert_eq!(loaded.add_documents(&path_documents()).unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn test_mapped_layout_roundtrip() {
+        let config = GraphConfig {
+            use_hnsw: true,
+            ..GraphConfig::default()
+        };
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("graph");
+        store.save_mapped(dir.to_str().unwrap()).unwrap();
+
+        let mut opened = NumpyGraphStore::open_mapped(dir.to_str().unwrap()).unwrap();
+        assert!(opened.text_vectors.is_mapped() || cfg!(target_endian = "big"));
+        let query = store.text_embedding(2);
+        assert_eq!(opened.search_similar_texts(query, 3), store.search_similar_texts(query, 3));
+        assert_eq!(opened.keyword_path(0, 1), store.keyword_path(0, 1));
+
+        opened.set_embedder(hash_embedder(&config)).unwrap();
+        let added = opened
+            .add_documents(&[Document {
+                text: "trains need electricity daily".to_string(),
+                ..path_documents()[1].clone()
+            }])
+            .unwrap();
+        assert_eq!(added, vec![3]);
+        // The new row is kept on the heap without copying the mapped ones
+        assert!(opened.text_vectors.is_mapped() || cfg!(target_endian = "big"));
+        assert_eq!(opened.text_embedding(2), store.text_embedding(2));
+    }
+
+    #[test]
+    fn test_binary_snapshot_roundtrip() {
+        let config = GraphConfig {
+            use_hnsw: true,
+            ..GraphConfig::default()
+        };
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let json = temp_dir.path().join("graph.json");
+        store.save(json.to_str().unwrap()).unwrap();
+        for name in ["graph.vkg", "graph.vkgz"] {
+            let path = temp_dir.path().join(name);
+            store.save(path.to_str().unwrap()).unwrap();
+            let size = std::fs::metadata(&path).unwrap().len();
+            assert!(size < std::fs::metadata(&json).unwrap().len());
+
+            let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+            assert_eq!(loaded.text_embedding(1), store.text_embedding(1));
+            assert_eq!(loaded.keyword_path(0, 1), store.keyword_path(0, 1));
+        }
+
+        // Explicit formats override the extension
+        let path = temp_dir.path().join("graph.dat");
+        store.save_as(path.to_str().unwrap(), SnapshotFormat::Json).unwrap();
+        assert!(NumpyGraphStore::load_as(path.to_str().unwrap(), SnapshotFormat::Json).is_ok());
+        assert!(NumpyGraphStore::load_as(path.to_str().unwrap(), SnapshotFormat::Binary { compressed: false }).is_err());
+    }
+
+    #[test]
+    fn test_load_rejects_inconsistent_snapshot() {
+        let config = GraphConfig {
+            use_hnsw: true,
+            ..GraphConfig::default()
+        };
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("graph.json");
+
+        let mut snapshot = store.to_snapshot();
+        snapshot.keywords.pop();
+        std::fs::write(&path, serde_json::to_string(&snapshot).unwrap()).unwrap();
+        assert!(NumpyGraphStore::load(path.to_str().unwrap()).is_err());
+
+        let mut snapshot = store.to_snapshot();
+        snapshot.config.as_mut().unwrap().embedding_dim = 16;
+        std::fs::write(&path, serde_json::to_string(&snapshot).unwrap()).unwrap();
+        assert!(NumpyGraphStore::load(path.to_str().unwrap()).is_err());
+
+        // Damaged sparse matrices, paths and vector indexes are reported, not indexed out of bounds
+        let n_keywords = store.get_keywords().len();
+        for (pointer, value) in [
+            ("/tf_mat/indptr/0", 1),
+            ("/keyword_edges/kept/0/0/0", 999_999),
+            ("/pred_mat/data/0", n_keywords),
+            ("/text_index/links/0/0/0", 12345),
+            ("/keyword_index/entry_point", n_keywords),
+        ] {
+            let mut snapshot = serde_json::to_value(store.to_snapshot()).unwrap();
+            *snapshot.pointer_mut(pointer).unwrap() = value.into();
+            std::fs::write(&path, snapshot.to_string()).unwrap();
+            assert!(
+                matches!(NumpyGraphStore::load(path.to_str().unwrap()), Err(KgError::CorruptSnapshot(_))),
+                "{}",
+                pointer
+            );
+        }
+
+        let mut snapshot = serde_json::to_value(store.to_snapshot()).unwrap();
+        // Too large for an f32, so it is read as infinity
+        *snapshot.pointer_mut("/text_vectors/data/0").unwrap() = 1e39.into();
+        std::fs::write(&path, snapshot.to_string()).unwrap();
+        assert!(matches!(NumpyGraphStore::load(path.to_str().unwrap()), Err(KgError::NanEmbedding(_))));
+    }
+
+    #[test]
+    fn test_load_legacy_snapshot_rebuilds_relationships() {
+        let config = GraphConfig::default();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        // Older snapshots kept each embedding inside its node
+        let mut legacy = serde_json::json!({
+            "texts": store.get_texts(),
+            "keywords": store.get_keywords(),
+        });
+        for (i, text) in legacy["texts"].as_array_mut().unwrap().iter_mut().enumerate() {
+            text["embedding"] = serde_json::json!(store.text_embedding(i));
+        }
+        for (i, keyword) in legacy["keywords"].as_array_mut().unwrap().iter_mut().enumerate() {
+            keyword["embedding"] = serde_json::json!(store.keyword_embedding(i));
+        }
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("graph.json");
+        std::fs::write(&path, legacy.to_string()).unwrap();
+
+        let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.text_embedding(2), store.text_embedding(2));
+        for keyword in store.get_keywords() {
+            assert_eq!(
+                loaded.get_keyword_related_texts(keyword.id, 10),
+                store.get_keyword_related_texts(keyword.id, 10)
+            );
+        }
+    }
+
+    #[test]
+    fn test_search_bounds() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Doc 1".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "Doc 2".to_string(),
+                source: SourceInfo {
+                    filename: "doc2.txt".to_string(),
+                    page_num: Some(2),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(1),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        // Test k larger than dataset
+        let query_vec = vec![0.0; config.embedding_dim];
+        let results = store.search_similar_texts(&query_vec, 100);
+        assert_eq!(results.len(), store.get_texts().len());
+    }
+
+    #[test]
+    fn test_empty_store_search() {
+        let store = NumpyGraphStore::new();
+        let query_vec = vec![0.0; 128];
+        
+        let text_results = store.search_similar_texts(&query_vec, 5);
+        assert!(text_results.is_empty());
+    }
+
+    #[test]
+    fn test_zero_k_search() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Doc 1".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let query_vec = vec![0.0; config.embedding_dim];
+        let results = store.search_similar_texts(&query_vec, 0);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_monotonic_distances() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Doc 1".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "Doc 2".to_string(),
+                source: SourceInfo {
+                    filename: "doc2.txt".to_string(),
+                    page_num: Some(2),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(1),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "Doc 3".to_string(),
+                source: SourceInfo {
+                    filename: "doc3.txt".to_string(),
+                    page_num: Some(3),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(2),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let query_vec = vec![0.0; config.embedding_dim];
+        let results = store.search_similar_texts(&query_vec, 5);
+
+        // Check distances are non-decreasing
+        for i in 0..results.len().saturating_sub(1) {
+            assert!(results[i].1 <= results[i + 1].1 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_finite_distances() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Doc 1".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let query_vec = vec![0.0; config.embedding_dim];
+        let results = store.search_similar_texts(&query_vec, 5);
+
+        for (_, dist) in results {
+            assert!(dist.is_finite());
+        }
+    }
+
+    #[test]
+    fn test_duplicate_removal() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Unique text".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "Unique text".to_string(), // Duplicate
+                source: SourceInfo {
+                    filename: "doc2.txt".to_string(),
+                    page_num: Some(2),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(1),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        // Should have merged the duplicate, keeping both locations
+        assert_eq!(store.get_texts().len(), 1);
+        let node = &store.get_texts()[0];
+        let sources: Vec<&SourceInfo> = node.sources().collect();
+        assert_eq!(sources, vec![&documents[0].source, &documents[1].source]);
+
+        // Re-ingesting a known location adds nothing, a new one is merged
+        let mut again = documents.clone();
+        again[1].source.filename = "doc3.txt".to_string();
+        assert!(store.add_documents(&again).unwrap().is_empty());
+        let filenames: Vec<&str> = store.get_texts()[0].sources().map(|s| s.filename.as_str()).collect();
+        assert_eq!(filenames, vec!["doc1.txt", "doc2.txt", "doc3.txt"]);
+
+        // Dropping a file keeps the text while another file still has it
+        assert_eq!(store.remove_by_source("doc1.txt"), 0);
+        assert_eq!(store.get_texts()[0].source.filename, "doc2.txt");
+        assert_eq!(store.remove_by_source("doc2.txt"), 0);
+        assert!(store.get_texts()[0].other_sources.is_empty());
+        assert_eq!(store.remove_by_source("doc3.txt"), 1);
+        assert!(store.is_text_removed(0));
+    }
+
+    #[test]
+    fn test_near_duplicates() {
+        let body = "Quarterly revenue grew by twelve percent while operating costs fell across every region, \
+                    driven mostly by lower energy prices and a smaller logistics bill than last year";
+        let documents: Vec<Document> = [
+            body.to_string(),
+            format!("{}\n\nPage 2", body),
+            "Pasta recipes from the north of Italy, with fresh eggs and flour".to_string(),
+            format!("{}.   Page 3", body.replace(' ', "  ")),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| Document {
+            text,
+            source: SourceInfo {
+                filename: format!("report{}.txt", i),
+                page_num: Some(i as u32 + 1),
+                file_type: "txt".to_string(),
+                chunk_idx: Some(0),
+                char_range: None,
+                tags: Vec::new(),
+            },
+            metadata: Metadata::new(),
+        })
+        .collect();
+        let mut config = GraphConfig {
+            embedding_dim: 16,
+            ..GraphConfig::default()
+        };
+
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+        assert_eq!(store.get_texts().len(), 4);
+        let mut pairs: Vec<(usize, usize)> = store.near_duplicates().iter().map(|p| (p.text, p.duplicate)).collect();
+        pairs.sort_unstable();
+        assert_eq!(pairs, vec![(0, 1), (0, 3), (1, 3)]);
+
+        // Signatures follow removal, compaction and reloading
+        let pairs = |store: &NumpyGraphStore| -> Vec<(usize, usize)> {
+            store.near_duplicates().iter().map(|p| (p.text, p.duplicate)).collect()
+        };
+        store.remove_text(1);
+        assert_eq!(pairs(&store), vec![(0, 3)]);
+        store.compact();
+        assert_eq!(pairs(&store), vec![(0, 2)]);
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("graph.vkg");
+        store.save(path.to_str().unwrap()).unwrap();
+        assert_eq!(pairs(&NumpyGraphStore::load(path.to_str().unwrap()).unwrap()), vec![(0, 2)]);
+
+        config.dedup.near_duplicates = NearDuplicateMode::Report;
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+        assert_eq!(store.get_texts().len(), 4);
+
+        config.dedup.near_duplicates = NearDuplicateMode::Merge;
+        store.build_kg(&documents[..3], &config, hash_embedder(&config)).unwrap();
+        assert_eq!(store.get_texts().len(), 2);
+        assert_eq!(store.add_documents(&documents[3..]).unwrap(), Vec::<usize>::new());
+        let filenames: Vec<&str> = store.get_texts()[0].sources().map(|s| s.filename.as_str()).collect();
+        assert_eq!(filenames, vec!["report0.txt", "report1.txt", "report3.txt"]);
+        assert!(store.near_duplicates().is_empty());
+
+        config.dedup.threshold = 1.0;
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+        assert_eq!(store.get_texts().len(), 4);
+    }
+
+    #[test]
+    fn test_provenance_preserved() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Test document".to_string(),
+                source: SourceInfo {
+                    filename: "test.pdf".to_string(),
+                    page_num: Some(5),
+                    file_type: "pdf".to_string(),
+                    chunk_idx: Some(2),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let sources = store.get_sources();
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].filename, "test.pdf");
+        assert_eq!(sources[0].page_num, Some(5));
+        assert_eq!(sources[0].file_type, "pdf");
+        assert_eq!(sources[0].chunk_idx, Some(2));
+    }
+
+    #[test]
+    fn test_keyword_related_texts_bounds() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Doc 1 with some words".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "Doc 2 with different words".to_string(),
+                source: SourceInfo {
+                    filename: "doc2.txt".to_string(),
+                    page_num: Some(2),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(1),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        // Test with k larger than available texts
+        if !store.get_keywords().is_empty() {
+            let keyword_idx = 0;
+            let results = store.get_keyword_related_texts(keyword_idx, 100);
+            assert!(results.len() <= store.get_texts().len());
+        }
+    }
+
+    #[test]
+    fn test_keyword_related_texts_ranked_by_bm25() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "rust compilers optimise rust programs written in rust".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "python programs mention rust once".to_string(),
+                source: SourceInfo {
+                    filename: "doc2.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "gardening tips for spring".to_string(),
+                source: SourceInfo {
+                    filename: "doc3.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let rust = store.get_keywords().iter().find(|k| k.text == "rust").unwrap().id;
+        let related = store.get_keyword_related_texts(rust, 10);
+        assert_eq!(related, vec![0, 1]);
+        assert!(store.get_association(0, rust) > store.get_association(1, rust));
+        assert_eq!(store.get_association(2, rust), 0.0);
+    }
+
+    #[test]
+    fn test_adjacent_keywords_bounds() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Doc 1".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        if !store.get_keywords().is_empty() {
+            let keyword_idx = 0;
+            let adjacent = store.get_adjacent_keywords(keyword_idx, 100);
+            // Should be bounded by available keywords (excluding self)
+            assert!(adjacent.len() <= store.get_keywords().len().saturating_sub(1));
+        }
+    }
+
+    #[test]
+    fn test_adjacent_keywords_from_cooccurrence() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "graph database storage engine".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "graph database query planner".to_string(),
+                source: SourceInfo {
+                    filename: "doc2.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "baking bread needs flour".to_string(),
+                source: SourceInfo {
+                    filename: "doc3.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let id = |word: &str| store.get_keywords().iter().find(|k| k.text == word).unwrap().id;
+        let adjacent = store.get_adjacent_keywords(id("graph"), 10);
+        let neighbours: Vec<usize> = adjacent.iter().map(|&(i, _)| i).collect();
+
+        assert!(neighbours.contains(&id("database")));
+        assert!(neighbours.contains(&id("storage")));
+        assert!(!neighbours.contains(&id("bread")));
+        assert!(!neighbours.contains(&id("graph")));
+        // "database" always appears with "graph", "storage" only half the time
+        assert_eq!(adjacent[0].0, id("database"));
+        for pair in adjacent.windows(2) {
+            assert!(pair[0].1 >= pair[1].1);
+        }
+    }
+
+    #[test]
+    fn test_similarity_edges() {
+        let config = GraphConfig {
+            similarity_edges: true,
+            connect_threshold: 2.0,
+            ..GraphConfig::default()
+        };
+        let documents = vec![
+            Document {
+                text: "alpha beta".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+            Document {
+                text: "gamma delta".to_string(),
+                source: SourceInfo {
+                    filename: "doc2.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let n_keywords = store.get_keywords().len();
+        for keyword in store.get_keywords() {
+            assert_eq!(store.get_adjacent_keywords(keyword.id, 100).len(), n_keywords - 1);
+        }
+
+        // Keywords added later are linked from both ends
+        let mut more = documents[..1].to_vec();
+        more[0].text = "epsilon".to_string();
+        store.add_documents(&more).unwrap();
+        let n_keywords = store.get_keywords().len();
+        for keyword in store.get_keywords() {
+            assert_eq!(store.get_adjacent_keywords(keyword.id, 100).len(), n_keywords - 1);
+        }
+    }
+
+    fn path_documents() -> Vec<Document> {
+        ["solar panels generate electricity", "electricity powers trains", "pasta recipes"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Document {
+                text: text.to_string(),
+                source: SourceInfo {
+                    filename: format!("doc{}.txt", i + 1),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_keyword_config_shapes_keywords() {
+        let mut config = GraphConfig {
+            embedding_dim: 16,
+            ..GraphConfig::default()
+        };
+        config.keywords.stemming = true;
+        config.keywords.keyphrases = KeyphraseMethod::Rake;
+        config.keywords.max_keywords_per_text = Some(4);
+        let mut documents = path_documents();
+        documents[1].text = "Electricity, from the grid, powers trains.".to_string();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let keywords: Vec<&str> = store.get_keywords().iter().map(|k| k.text.as_str()).collect();
+        assert!(keywords.contains(&"power train"), "{:?}", keywords);
+        assert!(keywords.contains(&"pasta recip"));
+        assert!(!keywords.iter().any(|k| k.contains("from") || k.contains(',')));
+        let electricity = keywords.iter().position(|&k| k == "electr").unwrap();
+        assert_eq!(store.get_keyword_related_texts(electricity, 10).len(), 2);
+        for text in 0..3 {
+            let linked = (0..keywords.len()).filter(|&kw| store.get_association(text, kw) > 0.0).count();
+            assert!(linked <= 4);
+        }
+    }
+
+    #[test]
+    fn test_keyword_ids_deterministic_and_stable() {
+        let config = GraphConfig {
+            embedding_dim: 16,
+            ..GraphConfig::default()
+        };
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut snapshots = Vec::new();
+        for run in 0..2 {
+            let mut store = NumpyGraphStore::new();
+            store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+            let path = temp_dir.path().join(format!("run{}.vkg", run));
+            store.save(path.to_str().unwrap()).unwrap();
+            snapshots.push(std::fs::read(&path).unwrap());
+        }
+        assert_eq!(snapshots[0], snapshots[1]);
+
+        let mut store = NumpyGraphStore::new();
+        let documents = path_documents();
+        store.build_kg(&documents[..2], &config, hash_embedder(&config)).unwrap();
+        let ids = |store: &NumpyGraphStore| -> Vec<(usize, String)> {
+            store.get_keywords().iter().map(|k| (k.id, k.text.clone())).collect()
+        };
+        let before = ids(&store);
+        let keywords: Vec<&str> = before.iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(keywords, vec!["solar", "panels", "generate", "electricity", "powers", "trains"]);
+
+        store.add_documents(&documents[2..]).unwrap();
+        assert_eq!(ids(&store)[..before.len()], before[..]);
+        assert_eq!(store.keyword_id("pasta"), Some(before.len()));
+        assert_eq!(store.find_keyword("  Electricity!"), store.keyword_id("electricity"));
+        assert_eq!(store.find_keyword("the"), None);
+
+        store.remove_by_source("doc3.txt");
+        assert_eq!(store.keyword_id("pasta"), None);
+        store.compact();
+        assert_eq!(store.keyword_id("trains"), Some(5));
+        let path = temp_dir.path().join("compacted.vkg");
+        store.save(path.to_str().unwrap()).unwrap();
+        let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.keyword_id("trains"), Some(5));
+    }
+
+    #[test]
+    fn test_search_text_embeds_query() {
+        let config = GraphConfig {
+            embedding_dim: 32,
+            ..GraphConfig::default()
+        };
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+
+        let hits = store.search_text("electricity powers trains", 2).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].id, hits[0].source.filename.as_str()), (1, "doc2.txt"));
+        assert!((hits[0].score - 1.0).abs() < 1e-5);
+        assert_eq!(hits[0].snippet, "electricity powers trains");
+        assert!(hits[1].score < hits[0].score);
+
+        let keywords = store.search_keywords_text("trains", 1).unwrap();
+        assert_eq!(keywords[0].text, "trains");
+        assert_eq!(Some(keywords[0].id), store.keyword_id("trains"));
+
+        let mut no_embedder = store.clone();
+        no_embedder.embedder = None;
+        assert!(no_embedder.search_text("trains", 1).is_err());
+    }
+
+    #[test]
+    fn test_hybrid_search_matches_identifiers() {
+        let config = GraphConfig {
+            embedding_dim: 32,
+            ..GraphConfig::default()
+        };
+        let mut documents = path_documents();
+        documents[2].text = "printer reports error E1234".to_string();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let lexical = store.lexical_search("what does e1234 mean", 10);
+        assert_eq!(lexical.len(), 1);
+        assert_eq!(lexical[0].0, 2);
+
+        let lexical_only = HybridConfig {
+            vector_weight: 0.0,
+            ..HybridConfig::default()
+        };
+        let hits = store.hybrid_search("E1234", 3, &lexical_only).unwrap();
+        assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), vec![2]);
+
+        let vector_only = HybridConfig {
+            lexical_weight: 0.0,
+            ..HybridConfig::default()
+        };
+        let hits = store.hybrid_search("electricity powers trains", 3, &vector_only).unwrap();
+        let vector_ids: Vec<usize> = store.search_text("electricity powers trains", 3).unwrap().iter().map(|h| h.id).collect();
+        assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), vector_ids);
+
+        for fusion in [Fusion::Rrf, Fusion::Linear] {
+            let weighted = HybridConfig {
+                fusion,
+                lexical_weight: 4.0,
+                ..HybridConfig::default()
+            };
+            let hits = store.hybrid_search("error E1234", 3, &weighted).unwrap();
+            assert_eq!(hits[0].id, 2, "{:?}", fusion);
+            assert_eq!(hits.len(), 3);
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("graph.vkg");
+        store.save(path.to_str().unwrap()).unwrap();
+        let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.lexical_search("E1234", 10), store.lexical_search("E1234", 10));
+
+        store.remove_text(2);
+        assert!(store.lexical_search("E1234", 10).is_empty());
+        // Removed texts stay out of the statistics after a reload
+        store.save(path.to_str().unwrap()).unwrap();
+        let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.lexical_search("trains", 10), store.lexical_search("trains", 10));
+        store.compact();
+        assert_eq!(loaded.lexical_search("trains", 10)[0].1, store.lexical_search("trains", 10)[0].1);
+        assert_eq!(store.lexical_search("trains", 10)[0].0, 1);
+    }
+
+    #[test]
+    fn test_filtered_search_returns_k_matches() {
+        let documents: Vec<Document> = (0..60)
+            .map(|i| Document {
+                text: format!("report section {} on quarterly figures", i),
+                source: SourceInfo {
+                    filename: format!("report{}.txt", i % 3),
+                    page_num: Some(i / 3),
+                    file_type: if i % 2 == 0 { "txt" } else { "md" }.to_string(),
+                    chunk_idx: Some(i as usize),
+                    char_range: None,
+                    tags: if i % 10 == 0 { vec!["audited".to_string()] } else { Vec::new() },
+                },
+                metadata: Metadata::new(),
+            })
+            .collect();
+        for use_hnsw in [false, true] {
+            let config = GraphConfig {
+                embedding_dim: 16,
+                use_hnsw,
+                hnsw_ef_search: 4,
+                ..GraphConfig::default()
+            };
+            let mut store = NumpyGraphStore::new();
+            store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+            let query = store.text_embedding(0).to_vec();
+
+            let filter = Filter::filename("report1.txt").and(Filter::pages(2..=12));
+            let hits = store.search_similar_texts_filtered(&query, 5, &filter);
+            assert_eq!(hits.len(), 5, "use_hnsw = {}", use_hnsw);
+            assert!(hits.iter().all(|&(id, _)| filter.matches(&store.get_texts()[id])));
+            assert!(hits.windows(2).all(|w| w[0].1 <= w[1].1));
+
+            let audited = store.search_text_filtered("quarterly figures", 10, &Filter::tag("audited")).unwrap();
+            assert_eq!(audited.len(), 6);
+
+            let markdown = Filter::file_type("md").and(Filter::chunks(0..=9));
+            assert_eq!(store.lexical_search_filtered("report", 10, &markdown).len(), 5);
+            let hybrid = HybridConfig {
+                filter: Some(markdown.clone()),
+                ..HybridConfig::default()
+            };
+            let hits = store.hybrid_search("quarterly", 10, &hybrid).unwrap();
+            assert_eq!(hits.len(), 5);
+            assert!(hits.iter().all(|hit| markdown.matches(&store.get_texts()[hit.id])));
+
+            store.remove_text(1);
+            assert_eq!(store.search_similar_texts_filtered(&query, 10, &markdown).len(), 4);
+        }
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let config = GraphConfig {
+            embedding_dim: 16,
+            ..GraphConfig::default()
+        };
+        let mut documents = path_documents();
+        for (i, doc) in documents.iter_mut().enumerate() {
+            doc.metadata.insert("author".to_string(), if i == 2 { "Bob" } else { "Ada" }.into());
+            doc.metadata.insert("created_at".to_string(), MetadataValue::Int(2020 + i as i64));
+            doc.metadata.insert("access".to_string(), vec![format!("team{}", i)].into());
+        }
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents[..2], &config, hash_embedder(&config)).unwrap();
+        store.add_documents(&documents[2..]).unwrap();
+        assert_eq!(store.get_texts()[2].metadata, documents[2].metadata);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        for name in ["graph.vkg", "graph.json"] {
+            let path = temp_dir.path().join(name);
+            store.save(path.to_str().unwrap()).unwrap();
+            let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+            for (text, doc) in loaded.get_texts().iter().zip(&documents) {
+                assert_eq!(text.metadata, doc.metadata, "{}", name);
+            }
+        }
+
+        let recent = Filter::metadata("author", "Ada").and(Filter::MetadataRange {
+            key: "created_at".to_string(),
+            min: Some(MetadataValue::Int(2021)),
+            max: None,
+        });
+        let hits = store.search_text_filtered("electricity", 3, &recent).unwrap();
+        assert_eq!(hits.iter().map(|hit| hit.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(hits[0].metadata["author"], MetadataValue::from("Ada"));
+        let team2 = store.lexical_search_filtered("pasta", 3, &Filter::metadata("access", "team2"));
+        assert_eq!(team2.len(), 1);
+    }
+
+    #[test]
+    fn test_metadata_per_source() {
+        let config = GraphConfig {
+            embedding_dim: 16,
+            ..GraphConfig::default()
+        };
+        let report = "quarterly report for the board";
+        let tenant = |name: &str| {
+            let mut doc = path_documents().remove(0);
+            doc.text = report.to_string();
+            doc.source.filename = format!("tenant{}.txt", name);
+            doc.metadata.insert("tenant".to_string(), name.into());
+            doc
+        };
+        let mut documents = path_documents();
+        documents.extend([tenant("A"), tenant("B")]);
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+        store.add_documents(&[tenant("C")]).unwrap();
+        let node = &store.get_texts()[3];
+        assert_eq!(node.metadata["tenant"], MetadataValue::from("A"));
+        let others: Vec<&MetadataValue> = node.other_metadata.iter().map(|m| &m["tenant"]).collect();
+        assert_eq!(others, vec![&MetadataValue::from("B"), &MetadataValue::from("C")]);
+
+        // A filtered hit shows only the matching locations, with their metadata
+        let only = |name: &str| Filter::metadata("tenant", name);
+        let a_hits = store.search_text_filtered(report, 1, &only("A")).unwrap();
+        assert_eq!(a_hits[0].source.filename, "tenantA.txt");
+        assert!(a_hits[0].other_sources.is_empty());
+        let b_hits = store.search_text_filtered(report, 1, &only("B")).unwrap();
+        assert_eq!((b_hits[0].id, b_hits[0].source.filename.as_str()), (3, "tenantB.txt"));
+        assert_eq!(b_hits[0].metadata["tenant"], MetadataValue::from("B"));
+        assert!(b_hits[0].other_sources.is_empty());
+        let all_hits = store.search_text(report, 1).unwrap();
+        let filenames: Vec<&str> = all_hits[0].other_sources.iter().map(|s| s.filename.as_str()).collect();
+        assert_eq!(filenames, vec!["tenantB.txt", "tenantC.txt"]);
+
+        let hybrid = HybridConfig {
+            filter: Some(only("C")),
+            ..HybridConfig::default()
+        };
+        assert_eq!(store.hybrid_search(report, 1, &hybrid).unwrap()[0].source.filename, "tenantC.txt");
+        let retrieval = RetrievalConfig {
+            filter: Some(only("B")),
+            ..RetrievalConfig::default()
+        };
+        let retrieved = store.retrieve(report, &retrieval).unwrap();
+        assert_eq!(retrieved[0].source.filename, "tenantB.txt");
+        assert!(retrieved[0].other_sources.is_empty());
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        for name in ["graph.vkg", "graph.json"] {
+            let path = temp_dir.path().join(name);
+            store.save(path.to_str().unwrap()).unwrap();
+            let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+            assert_eq!(loaded.get_texts()[3].other_metadata, store.get_texts()[3].other_metadata, "{}", name);
+        }
+
+        // The next location takes over its own metadata
+        assert_eq!(store.remove_by_source("tenantA.txt"), 0);
+        let node = &store.get_texts()[3];
+        assert_eq!(node.source.filename, "tenantB.txt");
+        assert_eq!(node.metadata["tenant"], MetadataValue::from("B"));
+        assert_eq!(node.other_metadata.len(), 1);
+        assert!(store.lexical_search_filtered("quarterly", 3, &only("A")).is_empty());
+        assert_eq!(store.lexical_search_filtered("quarterly", 3, &only("C")).len(), 1);
+    }
+
+    #[test]
+    fn test_keyword_path() {
+        let config = GraphConfig::default();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+
+        let id = |word: &str| store.get_keywords().iter().find(|k| k.text == word).unwrap().id;
+        let path = store.keyword_path(id("solar"), id("trains")).unwrap();
+        assert_eq!(path.keywords, vec![id("solar"), id("electricity"), id("trains")]);
+        assert_eq!(path.texts, vec![vec![0], vec![1]]);
+
+        let to_self = store.keyword_path(id("solar"), id("solar")).unwrap();
+        assert_eq!(to_self.keywords, vec![id("solar")]);
+        assert!(to_self.texts.is_empty());
+
+        assert!(store.keyword_path(id("solar"), id("pasta")).is_none());
+        assert!(store.keyword_path(id("solar"), store.get_keywords().len()).is_none());
+    }
+
+    #[test]
+    fn test_keyword_path_without_pred_mat() {
+        let config = GraphConfig {
+            max_pred_keywords: 0,
+            ..GraphConfig::default()
+        };
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+
+        let id = |word: &str| store.get_keywords().iter().find(|k| k.text == word).unwrap().id;
+        let path = store.keyword_path(id("trains"), id("solar")).unwrap();
+        assert_eq!(path.keywords, vec![id("trains"), id("electricity"), id("solar")]);
+
+        let short = GraphConfig {
+            max_path_hops: 1,
+            ..config
+        };
+        let mut short_store = NumpyGraphStore::new();
+        short_store.build_kg(&path_documents(), &short, hash_embedder(&short)).unwrap();
+        let id = |word: &str| short_store.get_keywords().iter().find(|k| k.text == word).unwrap().id;
+        assert!(short_store.keyword_path(id("trains"), id("solar")).is_none());
+    }
+
+    #[test]
+    fn test_hnsw_search_matches_exact() {
+        let config = GraphConfig {
+            use_hnsw: true,
+            embedding_dim: 32,
+            ..GraphConfig::default()
+        };
+        let documents: Vec<Document> = (0..200)
+            .map(|i| Document {
+                text: format!("document number {} about topic {}", i, i % 7),
+                source: SourceInfo {
+                    filename: format!("doc{}.txt", i),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            })
+            .collect();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        for text in store.get_texts().iter().step_by(20) {
+            let approx = store.search_similar_texts(store.text_embedding(text.id), 5);
+            let exact = store.search_similar_texts_exact(store.text_embedding(text.id), 5);
+            assert_eq!(approx.len(), 5);
+            assert_eq!(approx[0].0, text.id);
+            assert_eq!(approx[0].0, exact[0].0);
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("graph.json");
+        store.save(path.to_str().unwrap()).unwrap();
+        let loaded = NumpyGraphStore::load(path.to_str().unwrap()).unwrap();
+        assert!(loaded.text_index.is_some() && loaded.keyword_index.is_some());
+        let query = store.keyword_embedding(0);
+        assert_eq!(
+            loaded.search_similar_keywords(query, 3),
+            store.search_similar_keywords(query, 3)
+        );
+    }
+
+    /// Embedder that returns NaN for texts mentioning "nan"
+    struct NanEmbedder;
+
+    impl Embedder for NanEmbedder {
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+            let inner = HashEmbedder::new(16).embed(texts)?;
+            Ok(texts
+                .iter()
+                .zip(inner)
+                .map(|(text, vector)| if text.contains("nan") { vec![f32::NAN; 16] } else { vector })
+                .collect())
+        }
+
+        fn dimension(&self) -> usize {
+            16
+        }
+
+        fn model_id(&self) -> &str {
+            "nan"
+        }
+    }
+
+    #[test]
+    fn test_typed_errors() {
+        let config = GraphConfig {
+            embedding_dim: 16,
+            ..GraphConfig::default()
+        };
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, Arc::new(NanEmbedder)).unwrap();
+
+        assert!(matches!(
+            store.try_search_similar_texts(&[0.5; 8], 3),
+            Err(KgError::DimensionMismatch { expected: 16, found: 8 })
+        ));
+        assert!(store.search_similar_texts(&[0.5; 8], 3).is_empty());
+        let mut nan_query = vec![0.5; 16];
+        nan_query[3] = f32::NAN;
+        assert!(matches!(store.try_search_similar_keywords(&nan_query, 3), Err(KgError::NanEmbedding(_))));
+        assert_eq!(store.try_search_similar_texts(store.text_embedding(0), 1).unwrap()[0].0, 0);
+
+        assert!(matches!(store.search_text("banana", 3), Err(KgError::NanEmbedding(_))));
+        let banana = Document {
+            text: "banana bread".to_string(),
+            ..path_documents().remove(0)
+        };
+        assert!(matches!(store.add_documents(&[banana]), Err(KgError::NanEmbedding(_))));
+        assert_eq!(store.get_texts().len(), 3);
+
+        assert!(matches!(
+            store.text(3),
+            Err(KgError::IndexOutOfRange { kind: NodeKind::Text, index: 3, len: 3 })
+        ));
+        let n_keywords = store.get_keywords().len();
+        assert!(matches!(store.try_keyword_path(0, n_keywords), Err(KgError::IndexOutOfRange { .. })));
+        assert!(store.keyword_path(0, n_keywords).is_none());
+        assert!(matches!(
+            NumpyGraphStore::new().search_text("trains", 1),
+            Err(KgError::MissingEmbedder)
+        ));
+    }
+
+    /// Hash embedder that records how many strings it was asked to embed
+    struct CountingEmbedder {
+        inner: HashEmbedder,
+        calls: std::sync::Mutex<usize>,
+    }
+
+    impl Embedder for CountingEmbedder {
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+            *self.calls.lock().unwrap() += texts.len();
+            self.inner.embed(texts)
+        }
+
+        fn dimension(&self) -> usize {
+            self.inner.dimension()
+        }
+
+        fn model_id(&self) -> &str {
+            self.inner.model_id()
+        }
+    }
+
+    #[test]
+    fn test_add_documents_incremental() {
+        let config = GraphConfig {
+            use_hnsw: true,
+            ..GraphConfig::default()
+        };
+        let embedder = Arc::new(CountingEmbedder {
+            inner: HashEmbedder::new(config.embedding_dim),
+            calls: std::sync::Mutex::new(0),
+        });
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, embedder.clone()).unwrap();
+        let texts_before = store.get_texts().len();
+        let keywords_before: Vec<String> = store.get_keywords().iter().map(|k| k.text.clone()).collect();
+        *embedder.calls.lock().unwrap() = 0;
+
+        let mut more = path_documents();
+        more[0].text = "electricity prices rise".to_string();
+        let added = store.add_documents(&more).unwrap();
+
+        // Only the one unseen text and its two unseen keywords were embedded
+        assert_eq!(added, vec![texts_before]);
+        assert_eq!(*embedder.calls.lock().unwrap(), 3);
+        assert_eq!(store.get_texts().len(), texts_before + 1);
+        let keywords_after: Vec<String> = store.get_keywords().iter().map(|k| k.text.clone()).collect();
+        assert_eq!(keywords_after[..keywords_before.len()], keywords_before[..]);
+
+        let id = |word: &str| store.get_keywords().iter().find(|k| k.text == word).unwrap().id;
+        assert!(store.get_keyword_related_texts(id("prices"), 10).contains(&added[0]));
+        assert!(store
+            .get_adjacent_keywords(id("electricity"), 10)
+            .iter()
+            .any(|&(k, _)| k == id("prices")));
+
+        let new_text = &store.get_texts()[added[0]];
+        assert_eq!(store.search_similar_texts(store.text_embedding(new_text.id), 1)[0].0, added[0]);
+    }
+
+    #[test]
+    fn test_incremental_keyword_graph_rescores_touched_rows() {
+        let config = GraphConfig::default();
+        let batches: Vec<Vec<Document>> = [
+            vec!["electricity prices rise", "trains need electricity"],
+            vec!["pasta needs flour", "flour mills"],
+            vec!["solar trains", "new pasta recipes"],
+        ]
+        .iter()
+        .map(|texts| {
+            texts
+                .iter()
+                .map(|text| Document {
+                    text: text.to_string(),
+                    ..path_documents().remove(0)
+                })
+                .collect()
+        })
+        .collect();
+
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        let mut all = path_documents();
+        for batch in &batches {
+            let added = store.add_documents(batch).unwrap();
+            all.extend(batch.iter().cloned());
+
+            let mut rebuilt = NumpyGraphStore::new();
+            rebuilt.build_kg(&all, &config, hash_embedder(&config)).unwrap();
+            for i in 0..store.texts.len() {
+                for j in 0..store.keywords.len() {
+                    assert_eq!(store.get_association(i, j), rebuilt.get_association(i, j));
+                }
+            }
+            // Keywords of the new texts are rescored; the rest keep their weights
+            let tf_mat = store.tf_mat.clone().unwrap();
+            for (x, _) in added.iter().flat_map(|&i| tf_mat.row_iter(i)) {
+                assert_eq!(store.keyword_edges.kept[x], rebuilt.keyword_edges.kept[x]);
+            }
+            assert_paths_current(&store);
+        }
+
+        store.remove_text(0);
+        assert_paths_current(&store);
+    }
+
+    /// `pred_mat` matches a search over the store's current keyword edges
+    fn assert_paths_current(store: &NumpyGraphStore) {
+        let mut fresh = store.clone();
+        fresh.build_path_predecessors(&store.config);
+        assert_eq!(store.pred_mat, fresh.pred_mat);
+    }
+
+    #[test]
+    fn test_add_documents_requires_embedder() {
+        let mut store = NumpyGraphStore::new();
+        assert!(store.add_documents(&path_documents()).is_err());
+    }
+
+    #[test]
+    fn test_remove_by_source_and_compact() {
+        let config = GraphConfig {
+            use_hnsw: true,
+            ..GraphConfig::default()
+        };
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        let id = |store: &NumpyGraphStore, word: &str| {
+            store.get_keywords().iter().find(|k| k.text == word).unwrap().id
+        };
+        let (solar, electricity, trains) = (id(&store, "solar"), id(&store, "electricity"), id(&store, "trains"));
+        let solar_vec = store.keyword_embedding(solar).to_vec();
+        let first_text = store.text_embedding(0).to_vec();
+
+        assert_eq!(store.remove_by_source("doc1.txt"), 1);
+        assert_eq!(store.remove_by_source("doc1.txt"), 0);
+        assert!(store.is_text_removed(0));
+        assert_eq!(store.live_text_count(), 2);
+
+        // Keywords only found in doc1 are gone, shared ones survive
+        assert!(store.is_keyword_removed(solar));
+        assert!(!store.is_keyword_removed(electricity));
+        assert!(store.search_similar_texts(&first_text, 10).iter().all(|&(i, _)| i != 0));
+        assert!(store.search_similar_keywords(&solar_vec, 100).iter().all(|&(i, _)| i != solar));
+        assert_eq!(store.get_keyword_related_texts(electricity, 10), vec![1]);
+        assert!(store.get_adjacent_keywords(electricity, 10).iter().all(|&(k, _)| k != solar));
+        assert!(store.keyword_path(solar, trains).is_none());
+
+        let compaction = store.compact();
+        assert_eq!(compaction.text_ids, vec![None, Some(0), Some(1)]);
+        assert_eq!(compaction.keyword_ids[solar], None);
+        assert_eq!(store.get_texts().len(), 2);
+        assert_eq!(store.get_keywords().len(), store.live_keyword_count());
+        for (i, text) in store.get_texts().iter().enumerate() {
+            assert_eq!(text.id, i);
+            assert_eq!(store.search_similar_texts(store.text_embedding(i), 1)[0].0, i);
+        }
+        let (electricity, trains) = (id(&store, "electricity"), id(&store, "trains"));
+        assert_eq!(store.get_keyword_related_texts(electricity, 10), vec![0]);
+        assert_eq!(store.keyword_path(electricity, trains).unwrap().texts, vec![vec![0]]);
+    }
+
+    #[test]
+    fn test_stats_count_live_edges() {
+        let config = GraphConfig::default();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        let stats = store.stats();
+        assert_eq!((stats.texts, stats.live_texts), (3, 3));
+        assert!(stats.keyword_edges > 0);
+
+        store.remove_by_source("doc3.txt");
+        let after = store.stats();
+        assert_eq!((after.texts, after.live_texts), (3, 2));
+        assert_eq!(after.live_keywords, stats.live_keywords - 2);
+        assert_eq!(after.associations, stats.associations - 2);
+        // Only the removed keywords' edge goes; untouched rows keep their weights, through compaction too
+        assert_eq!(after.keyword_edges, stats.keyword_edges - 1);
+        let mut compacted = store.clone();
+        compacted.compact();
+        assert_eq!(after.keyword_edges, compacted.stats().keyword_edges);
+    }
+
+    #[test]
+    fn test_removed_texts_leave_keyword_graph() {
+        let config = GraphConfig::default();
+        let texts = [
+            "solar electricity v1",
+            "solar electricity v2",
+            "solar electricity v3",
+            "solar farms",
+            "electricity grids",
+            "pasta recipes",
+            "flour mills",
+        ];
+        let documents: Vec<Document> = texts
+            .iter()
+            .map(|text| Document {
+                text: text.to_string(),
+                ..path_documents().remove(0)
+            })
+            .collect();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+        let id = |word: &str| store.keyword_id(word).unwrap();
+        let (solar, electricity) = (id("solar"), id("electricity"));
+        assert!(store.get_adjacent_keywords(solar, 10).iter().any(|&(k, _)| k == electricity));
+
+        for text in 0..3 {
+            assert!(store.remove_text(text));
+        }
+        assert!(store.get_adjacent_keywords(solar, 10).iter().all(|&(k, _)| k != electricity));
+        assert!(store.keyword_path(solar, electricity).is_none());
+        for from in 0..store.get_keywords().len() {
+            for to in 0..store.get_keywords().len() {
+                if let Some(path) = store.keyword_path(from, to) {
+                    assert!(path.texts.iter().all(|texts| !texts.is_empty()), "{:?}", path);
+                }
+            }
+        }
+
+        let mut compacted = store.clone();
+        compacted.compact();
+        assert_eq!(store.stats().keyword_edges, compacted.stats().keyword_edges);
+        assert_eq!(store.get_keyword_related_texts(solar, 10), vec![3]);
+        let weight = store.get_association(3, solar);
+        assert_eq!(weight, compacted.get_association(0, compacted.keyword_id("solar").unwrap()));
+    }
+
+    #[test]
+    fn test_remove_text_then_readd() {
+        let config = GraphConfig::default();
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&path_documents(), &config, hash_embedder(&config)).unwrap();
+        let pasta = store.get_keywords().iter().find(|k| k.text == "pasta").unwrap().id;
+
+        assert!(store.remove_text(2));
+        assert!(!store.remove_text(2));
+        assert!(!store.remove_text(99));
+        assert!(store.is_keyword_removed(pasta));
+
+        // The same passage can be ingested again and revives its keywords
+        let added = store.add_documents(&path_documents()[2..]).unwrap();
+        assert_eq!(added, vec![3]);
+        assert!(!store.is_keyword_removed(pasta));
+        assert_eq!(store.get_keyword_related_texts(pasta, 10), vec![3]);
+    }
+
+    #[test]
+    fn test_identical_queries_deterministic() {
+        let config = GraphConfig::default();
+        let documents = vec![
+            Document {
+                text: "Doc 1".to_string(),
+                source: SourceInfo {
+                    filename: "doc1.txt".to_string(),
+                    page_num: Some(1),
+                    file_type: "txt".to_string(),
+                    chunk_idx: Some(0),
+                    char_range: None,
+                    tags: Vec::new(),
+                },
+                metadata: Metadata::new(),
+            },
+        ];
+        let mut store = NumpyGraphStore::new();
+        store.build_kg(&documents, &config, hash_embedder(&config)).unwrap();
+
+        let query_vec = vec![0.0; config.embedding_dim];
+
+        // Run query multiple times
+        let results1 = store.search_similar_texts(&query_vec, 5);
+        let results2 = store.search_similar_texts(&query_vec, 5);
+
+        assert_eq!(results1.len(), results2.len());
+        for i in 0..results1.len() {
+            assert!((results1[i].1 - results2[i].1).abs() < 1e-6);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,359 @@
+use memmap2::Mmap;
+use serde::de::Deserializer;
+use serde::ser::{SerializeSeq, SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::hnsw::VectorSet;
+use crate::KgError;
+
+/// First bytes of every embedding matrix file
+const MAGIC: &[u8; 8] = b"VKGVEC\0\0";
+
+const FORMAT_VERSION: u32 = 1;
+
+/// Header size; also the offset of the first float, which keeps rows aligned
+/// to a cache line inside the page-aligned mapping
+const HEADER_LEN: usize = 64;
+
+/// Rows per heap block
+const BLOCK_ROWS: usize = 1024;
+
+/// Row-major matrix holding one embedding per row
+///
+/// Leading rows may be read from a read-only memory-mapped file written by
+/// `write_to`; the others live on the heap in blocks of `BLOCK_ROWS` rows.
+/// Clones share the mapping and the blocks, and appending to a matrix whose
+/// last block is shared copies only that block, so a copy of a large matrix
+/// is cheap to grow.
+#[derive(Debug, Clone)]
+pub struct EmbeddingMatrix {
+    dim: usize,
+    rows: usize,
+    mapped: Option<Mapped>,
+    blocks: Vec<Arc<Vec<f32>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Mapped {
+    mmap: Arc<Mmap>,
+    rows: usize,
+}
+
+impl EmbeddingMatrix {
+    /// Empty matrix for vectors of length `dim`
+    pub fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            rows: 0,
+            mapped: None,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Length of every row
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Whether leading rows are read from a memory-mapped file
+    pub fn is_mapped(&self) -> bool {
+        self.mapped.is_some()
+    }
+
+    /// Embedding in row `i`
+    pub fn row(&self, i: usize) -> &[f32] {
+        let mapped_rows = self.mapped_rows();
+        if i < mapped_rows {
+            return &self.mapped_slice()[i * self.dim..(i + 1) * self.dim];
+        }
+        assert!(i < self.rows, "row {} of {}", i, self.rows);
+        let (block, offset) = ((i - mapped_rows) / BLOCK_ROWS, (i - mapped_rows) % BLOCK_ROWS);
+        &self.blocks[block][offset * self.dim..(offset + 1) * self.dim]
+    }
+
+    /// All rows, in order
+    pub fn rows(&self) -> impl Iterator<Item = &[f32]> + '_ {
+        (0..self.rows).map(|i| self.row(i))
+    }
+
+    /// Append a row; panics if its length is not `dim`
+    pub fn push(&mut self, row: &[f32]) {
+        assert_eq!(row.len(), self.dim, "row length must match matrix dimension");
+        if (self.rows - self.mapped_rows()).is_multiple_of(BLOCK_ROWS) {
+            self.blocks.push(Arc::new(Vec::with_capacity(BLOCK_ROWS * self.dim)));
+        }
+        // Copies the block first if a clone still holds it
+        Arc::make_mut(self.blocks.last_mut().unwrap()).extend_from_slice(row);
+        self.rows += 1;
+    }
+
+    /// Keep only the rows for which `keep` is true, preserving order
+    ///
+    /// The kept rows are copied to the heap.
+    pub fn retain_rows(&mut self, keep: &[bool]) {
+        let mut kept = Self::new(self.dim);
+        for i in (0..self.rows).filter(|&i| keep[i]) {
+            kept.push(self.row(i));
+        }
+        *self = kept;
+    }
+
+    fn mapped_rows(&self) -> usize {
+        self.mapped.as_ref().map_or(0, |mapped| mapped.rows)
+    }
+
+    /// Rows read from the mapping, concatenated
+    fn mapped_slice(&self) -> &[f32] {
+        let Some(mapped) = &self.mapped else {
+            return &[];
+        };
+        let len = mapped.rows * self.dim;
+        let bytes = &mapped.mmap[HEADER_LEN..HEADER_LEN + len * 4];
+        // SAFETY: `open` checked that the body is 4-byte aligned, long
+        // enough, and little-endian like this target, and the mapping is
+        // kept alive by `self`.
+        unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, len) }
+    }
+
+    /// Write the matrix as a file that `open` can map
+    ///
+    /// Fails on a non-finite value, leaving an incomplete file that `open`
+    /// rejects, so mapped rows need no check when they are opened.
+    pub fn write_to(&self, path: &Path) -> Result<(), KgError> {
+        let mut out = BufWriter::new(std::fs::File::create(path)?);
+        let mut header = [0u8; HEADER_LEN];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&(self.dim as u32).to_le_bytes());
+        header[16..24].copy_from_slice(&(self.rows as u64).to_le_bytes());
+        out.write_all(&header)?;
+        for (i, row) in self.rows().enumerate() {
+            if !row.iter().all(|x| x.is_finite()) {
+                return Err(KgError::NanEmbedding(format!("row {} written to {}", i, path.display())));
+            }
+            for value in row {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Memory-map a file written by `write_to` without copying its rows
+    ///
+    /// The file must not be modified while the matrix (or any clone) is alive.
+    pub fn open(path: &Path) -> Result<Self, KgError> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the mapping is read-only; callers are told not to modify the file
+        let mmap = unsafe { Mmap::map(&file)? };
+        let display = path.display();
+
+        if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+            return Err(KgError::CorruptSnapshot(format!("{} is not an embedding matrix file", display)));
+        }
+        let version = u32::from_le_bytes(mmap[8..12].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(KgError::UnsupportedVersion {
+                found: version,
+                supported: FORMAT_VERSION,
+            });
+        }
+        let dim = u32::from_le_bytes(mmap[12..16].try_into().unwrap()) as usize;
+        let rows = u64::from_le_bytes(mmap[16..24].try_into().unwrap()) as usize;
+
+        let expected = rows
+            .checked_mul(dim)
+            .and_then(|n| n.checked_mul(4))
+            .and_then(|n| n.checked_add(HEADER_LEN));
+        if expected != Some(mmap.len()) {
+            return Err(KgError::CorruptSnapshot(format!(
+                "{} holds {} bytes, header describes {} rows of {} floats",
+                display,
+                mmap.len(),
+                rows,
+                dim
+            )));
+        }
+
+        let aligned = (mmap.as_ptr() as usize + HEADER_LEN).is_multiple_of(std::mem::align_of::<f32>());
+        if cfg!(target_endian = "little") && aligned {
+            Ok(Self {
+                dim,
+                rows,
+                mapped: Some(Mapped {
+                    mmap: Arc::new(mmap),
+                    rows,
+                }),
+                blocks: Vec::new(),
+            })
+        } else {
+            let data: Vec<f32> = mmap[HEADER_LEN..]
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect();
+            Ok(Self::from_flat(dim, &data))
+        }
+    }
+
+    /// Heap matrix holding `data`, rows of `dim` concatenated
+    fn from_flat(dim: usize, data: &[f32]) -> Self {
+        let mut matrix = Self::new(dim);
+        if dim > 0 {
+            for row in data.chunks_exact(dim) {
+                matrix.push(row);
+            }
+        }
+        matrix
+    }
+}
+
+impl VectorSet for EmbeddingMatrix {
+    fn len(&self) -> usize {
+        self.rows
+    }
+
+    fn vector(&self, i: usize) -> &[f32] {
+        self.row(i)
+    }
+}
+
+impl Serialize for EmbeddingMatrix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("EmbeddingMatrix", 2)?;
+        state.serialize_field("dim", &self.dim)?;
+        state.serialize_field("data", &Flat(self))?;
+        state.end()
+    }
+}
+
+/// All rows of a matrix, serialized as one sequence of floats
+struct Flat<'a>(&'a EmbeddingMatrix);
+
+impl Serialize for Flat<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.rows * self.0.dim))?;
+        for value in self.0.rows().flatten() {
+            seq.serialize_element(value)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for EmbeddingMatrix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "EmbeddingMatrix")]
+        struct Owned {
+            dim: usize,
+            data: Vec<f32>,
+        }
+
+        let Owned { dim, data } = Owned::deserialize(deserializer)?;
+        if dim == 0 && !data.is_empty() || dim != 0 && data.len() % dim != 0 {
+            return Err(serde::de::Error::custom(format!(
+                "{} floats do not split into rows of {}",
+                data.len(),
+                dim
+            )));
+        }
+        Ok(Self::from_flat(dim, &data))
+    }
+}
+
+impl FromIterator<Vec<f32>> for EmbeddingMatrix {
+    /// Collect rows; the dimension is taken from the first row (0 if empty)
+    fn from_iter<I: IntoIterator<Item = Vec<f32>>>(iter: I) -> Self {
+        let mut iter = iter.into_iter().peekable();
+        let dim = iter.peek().map_or(0, Vec::len);
+        let mut matrix = Self::new(dim);
+        for row in iter {
+            matrix.push(&row);
+        }
+        matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> EmbeddingMatrix {
+        vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0], vec![7.0, 8.0, 9.0]]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_mapped_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("texts.f32");
+        sample().write_to(&path).unwrap();
+
+        let mut mapped = EmbeddingMatrix::open(&path).unwrap();
+        assert!(mapped.is_mapped() || cfg!(target_endian = "big"));
+        assert_eq!((mapped.len(), mapped.dim()), (3, 3));
+        assert_eq!(mapped.row(1), &[4.0, 5.0, 6.0]);
+
+        // Appended rows go to the heap behind the mapping
+        mapped.push(&[0.0, 0.0, 1.0]);
+        assert!(mapped.is_mapped() || cfg!(target_endian = "big"));
+        assert_eq!(mapped.row(2), &[7.0, 8.0, 9.0]);
+        assert_eq!(mapped.row(3), &[0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_clones_share_blocks() {
+        let matrix: EmbeddingMatrix = (0..=BLOCK_ROWS).map(|i| vec![i as f32; 2]).collect();
+        let mut copy = matrix.clone();
+        copy.push(&[-1.0, -1.0]);
+        // Only the block the new row went into is copied
+        assert!(Arc::ptr_eq(&matrix.blocks[0], &copy.blocks[0]));
+        assert!(!Arc::ptr_eq(&matrix.blocks[1], &copy.blocks[1]));
+        assert_eq!((matrix.len(), copy.len()), (BLOCK_ROWS + 1, BLOCK_ROWS + 2));
+        assert_eq!(copy.row(BLOCK_ROWS), &[BLOCK_ROWS as f32; 2]);
+        assert_eq!(copy.row(BLOCK_ROWS + 1), &[-1.0, -1.0]);
+    }
+
+    #[test]
+    fn test_write_rejects_non_finite_rows() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("texts.f32");
+        let mut matrix = sample();
+        matrix.push(&[1.0, f32::NAN, 0.0]);
+        assert!(matches!(matrix.write_to(&path), Err(KgError::NanEmbedding(_))));
+        assert!(EmbeddingMatrix::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_open_rejects_truncated_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("texts.f32");
+        sample().write_to(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        assert!(EmbeddingMatrix::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_retain_rows_and_serde() {
+        let mut matrix = sample();
+        matrix.retain_rows(&[true, false, true]);
+        assert_eq!(matrix.rows().flatten().copied().collect::<Vec<_>>(), [1.0, 2.0, 3.0, 7.0, 8.0, 9.0]);
+
+        let json = serde_json::to_string(&matrix).unwrap();
+        let back: EmbeddingMatrix = serde_json::from_str(&json).unwrap();
+        assert_eq!((back.len(), back.row(1)), (2, &[7.0, 8.0, 9.0][..]));
+    }
+}